@@ -1,6 +1,8 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 use super::banner::BANNER;
+use crate::core::format::ConfigFormat;
+use crate::utils::system::AURHelper;
 use crate::utils::validation::{validate_repo_format, validate_folder_path};
 use colored::*;
 
@@ -10,7 +12,7 @@ use colored::*;
     version,
     about = format!("{}\n{}", 
         BANNER,
-        "A modern dotfile manager for your archlinux setup".bright_cyan().bold()
+        "A modern dotfile manager for your Linux setup".bright_cyan().bold()
     ),
     help_template = format!("{{before-help}}{{about}}\n\n{}\n{{options}}\n\n{}\n{{after-help}}", 
         "Commands:".bright_yellow().bold(),
@@ -46,40 +48,177 @@ pub struct Args {
         help = "Remove all backup files from ~/.cache/dotty/dotty_backups"
     )]
     pub clean: bool,
+
+    /// Show package manager output while installing
+    #[arg(short, long, help_heading = "Installation Options")]
+    pub verbose: bool,
+
+    /// Answer yes to every confirmation
+    #[arg(short, long, global = true, help_heading = "Installation Options")]
+    pub yes: bool,
+
+    /// Never read from stdin, fail when a question needs an answer
+    #[arg(long, global = true, help_heading = "Installation Options")]
+    pub no_input: bool,
+
+    /// Pick the packages and files to install from a checklist
+    #[arg(short, long, help_heading = "Installation Options")]
+    pub select: bool,
+
+    /// Only install these packages and their files
+    #[arg(long, value_name = "PACKAGES", value_delimiter = ',', help_heading = "Installation Options")]
+    pub only: Vec<String>,
+
+    /// Install everything except these packages and their files
+    #[arg(long, value_name = "PACKAGES", value_delimiter = ',', help_heading = "Installation Options")]
+    pub except: Vec<String>,
+
+    /// Only copy files, skip installing packages
+    #[arg(long, conflicts_with = "packages_only", help_heading = "Installation Options")]
+    pub files_only: bool,
+
+    /// Only install packages, skip copying files
+    #[arg(long, help_heading = "Installation Options")]
+    pub packages_only: bool,
+
+    /// Show what would be installed, copied and changed without doing it
+    #[arg(long, help_heading = "Installation Options")]
+    pub dry_run: bool,
+
+    /// AUR helper to use instead of asking when none is installed
+    #[arg(long, value_enum, value_name = "HELPER", help_heading = "Installation Options")]
+    pub aur_helper: Option<AURHelper>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Validate dotty.yaml and report every problem found
+    Check {
+        /// Folder containing dotty.yaml (defaults to the current directory)
+        #[arg(value_name = "PATH", default_value = ".", value_parser = validate_folder_path)]
+        path: PathBuf,
+    },
+
+    /// Print the JSON Schema for dotty.yaml
+    Schema,
+
+    /// Show which deployed files differ from the repository and which services are not set up
+    Status {
+        /// Folder containing the configuration (defaults to the current directory)
+        #[arg(value_name = "PATH", default_value = ".", value_parser = validate_folder_path)]
+        path: PathBuf,
+
+        /// Show how modified files differ, with secret values masked
+        #[arg(long)]
+        diff: bool,
+    },
+
+    /// Convert the configuration between YAML, TOML and JSON
+    Convert {
+        /// Folder containing the configuration (defaults to the current directory)
+        #[arg(value_name = "PATH", default_value = ".", value_parser = validate_folder_path)]
+        path: PathBuf,
+
+        /// Format to convert to
+        #[arg(long, value_enum)]
+        to: ConfigFormat,
+
+        /// Print the converted configuration instead of replacing the file
+        #[arg(long)]
+        stdout: bool,
+    },
+
+    /// Check that the machine matches the `system` section of the configuration
+    Doctor {
+        /// Folder containing the configuration (defaults to the current directory)
+        #[arg(value_name = "PATH", default_value = ".", value_parser = validate_folder_path)]
+        path: PathBuf,
+    },
+
+    /// Remove packages and files dotty installed that are no longer configured
+    Prune {
+        /// Folder containing the configuration (defaults to the current directory)
+        #[arg(value_name = "PATH", default_value = ".", value_parser = validate_folder_path)]
+        path: PathBuf,
+
+        /// Also remove deployed files that no mapping points at anymore
+        #[arg(long)]
+        files: bool,
+    },
+
+    /// Upgrade dotty.yaml to the current format version in place
+    Migrate {
+        /// Folder containing dotty.yaml (defaults to the current directory)
+        #[arg(value_name = "PATH", default_value = ".", value_parser = validate_folder_path)]
+        path: PathBuf,
+
+        /// Only show the changes without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 impl Args {
     pub fn show_help_if_no_args() {
         println!("{}", BANNER);
-        println!("\n{}", "A modern dotfile manager for your archlinux setup".bright_cyan().bold());
+        println!("\n{}", "A modern dotfile manager for your Linux setup".bright_cyan().bold());
         
         println!("\n{}", "Commands:".bright_yellow().bold());
         println!("  {} Clone and install from GitHub", "dotty -r username/repo".bright_white());
         println!("  {} Install from local folder", "dotty -f ~/.dotfiles".bright_white());
         println!("  {} Clean backup files", "dotty -c".bright_white());
+        println!("  {} Validate dotty.yaml", "dotty check".bright_white());
         
         println!("\n{}", "Use dotty -h for more information".bright_blue());
     }
 
     pub fn show_help() {
         println!("{}", BANNER);
-        println!("\n{}", "A modern dotfile manager for your archlinux setup".bright_cyan().bold());
+        println!("\n{}", "A modern dotfile manager for your Linux setup".bright_cyan().bold());
         
         println!("\n{}", "Commands:".bright_yellow().bold());
         println!("  {} Clone and install from GitHub", "dotty -r, --repo <USERNAME/REPO>".bright_white());
         println!("  {} Install from local folder", "dotty -f, --folder <PATH>".bright_white());
         println!("  {} Clean backup files", "dotty -c, --clean".bright_white());
+        println!("  {} Show package manager output while installing", "dotty -v, --verbose".bright_white());
+        println!("  {} Pick packages and files from a checklist", "dotty -s, --select".bright_white());
+        println!("  {} Install only these packages and their files", "dotty --only <a,b>".bright_white());
+        println!("  {} Install everything but these packages", "dotty --except <a,b>".bright_white());
+        println!("  {} Only copy files or only install packages", "dotty --files-only, --packages-only".bright_white());
+        println!("  {} Show the installation plan without changing anything", "dotty --dry-run".bright_white());
+        println!("  {} Answer yes to every confirmation", "dotty -y, --yes".bright_white());
+        println!("  {} Never wait for input, fail instead", "dotty --no-input".bright_white());
+        println!("  {} AUR helper to use when none is installed", "dotty --aur-helper <paru|yay|builtin|none>".bright_white());
+        println!("  {} Validate dotty.yaml and report all problems", "dotty check [PATH]".bright_white());
+        println!("  {} Print the JSON Schema for dotty.yaml", "dotty schema".bright_white());
+        println!("  {} Show drifted files and services that are not set up", "dotty status [PATH]".bright_white());
+        println!("  {} Check the shell, groups, locale and timezone", "dotty doctor [PATH]".bright_white());
+        println!("  {} Remove packages and files no longer in the configuration", "dotty prune [PATH] [--files]".bright_white());
+        println!("  {} Upgrade dotty.yaml to the current format", "dotty migrate [PATH] [--dry-run]".bright_white());
+        println!("  {} Convert the configuration format", "dotty convert --to <yaml|toml|json> [PATH]".bright_white());
 
         println!("\n{}", "Examples:".bright_yellow().bold());
         println!("  {} Install dotfiles from GitHub:", "→".bright_blue());
         println!("    {}", "dotty -r neox1de/dotfiles".bright_white());
         
+        println!("  {} Apply only the Neovim and kitty configuration:", "→".bright_blue());
+        println!("    {}", "dotty -f ~/.dotfiles --only nvim,kitty --files-only".bright_white());
+
+        println!("  {} Check a configuration before installing:", "→".bright_blue());
+        println!("    {}", "dotty check ~/.dotfiles".bright_white());
+
         println!("  {} Clean up old backups:", "→".bright_blue());
         println!("    {}", "dotty -c".bright_white());
 
         println!("\n{}", "Additional Information:".bright_yellow().bold());
-        println!("  • Configuration is read from {}", "dotty.yaml".bright_white());
+        println!("  • Configuration is read from {}, {}, {} or {}",
+            "dotty.yaml".bright_white(), "dotty.yml".bright_white(),
+            "dotty.toml".bright_white(), "dotty.json".bright_white());
         println!("  • Backups are stored in {}", "~/.cache/dotty/dotty_backups".bright_white());
+        println!("  • Package manager logs are stored in {}", "~/.cache/dotty/logs".bright_white());
         println!("  • Version: {}", env!("CARGO_PKG_VERSION").bright_white());
     }
 } 
@@ -1,4 +1,4 @@
 mod args;
 mod banner;
 
-pub use args::Args; 
\ No newline at end of file
+pub use args::{Args, Command}; 
\ No newline at end of file
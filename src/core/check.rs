@@ -0,0 +1,703 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde_json::Value as Schema;
+use crate::core::config::DottyConfig;
+use crate::core::document::{self, Node, Position, Value};
+use crate::core::format::{self, ConfigFormat};
+use crate::core::migrate;
+use crate::core::version::VersionReq;
+use crate::utils::output::{print_error, print_warning};
+use crate::utils::secrets::referenced_secrets;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found in a configuration file
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub position: Option<Position>,
+}
+
+/// All problems found while checking a configuration file
+pub struct CheckReport {
+    pub file: PathBuf,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckReport {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics.iter().filter(|d| d.severity == severity).count()
+    }
+
+    pub fn print(&self) {
+        for diagnostic in &self.diagnostics {
+            let location = match diagnostic.position {
+                Some(p) => format!("{}:{}:{}", self.file.display(), p.line, p.column),
+                None => self.file.display().to_string(),
+            };
+            let message = format!("{}: {}", location, diagnostic.message);
+            match diagnostic.severity {
+                Severity::Error => print_error(message),
+                Severity::Warning => print_warning(message),
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Location of a value inside the document, e.g. `packages[0].files[1].source`
+#[derive(Clone, Default)]
+struct KeyPath(Vec<Segment>);
+
+impl KeyPath {
+    fn key(&self, key: &str) -> Self {
+        let mut path = self.clone();
+        path.0.push(Segment::Key(key.to_string()));
+        path
+    }
+
+    fn index(&self, index: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push(Segment::Index(index));
+        path
+    }
+
+    fn resolve<'a>(&self, root: &'a Node) -> Option<&'a Node> {
+        self.0.iter().try_fold(root, |node, segment| match segment {
+            Segment::Key(key) => node.get(key),
+            Segment::Index(index) => node.index(*index),
+        })
+    }
+}
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "<root>");
+        }
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if i == 0 => write!(f, "{}", key)?,
+                Segment::Key(key) => write!(f, ".{}", key)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Checker<'a> {
+    root: &'a Node,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, severity: Severity, path: &KeyPath, message: String) {
+        let position = path.resolve(self.root).map(|node| node.position);
+        self.diagnostics.push(Diagnostic { severity, message, position });
+    }
+
+    fn report_at(&mut self, severity: Severity, position: Position, message: String) {
+        self.diagnostics.push(Diagnostic { severity, message, position: Some(position) });
+    }
+}
+
+/// Checks the configuration file in `repo_path` and collects every problem found.
+///
+/// Only failing to find or read the file is returned as an error, everything
+/// else ends up as a diagnostic in the report.
+pub fn check_repo(repo_path: &Path) -> Result<CheckReport> {
+    let file = format::find_config(repo_path)?;
+    let format = ConfigFormat::from_path(&file)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported configuration file: {}", file.display()))?;
+
+    let source = std::fs::read_to_string(&file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+
+    let diagnostics = check_source(&source, format, repo_path);
+    Ok(CheckReport { file, diagnostics })
+}
+
+fn check_source(source: &str, format: ConfigFormat, repo_path: &Path) -> Vec<Diagnostic> {
+    let root = match document::parse(format, source) {
+        Ok(root) => root,
+        Err(e) => {
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                message: format!("syntax error: {}", e.message),
+                position: Some(e.position),
+            }];
+        }
+    };
+
+    let mut checker = Checker { root: &root, diagnostics: Vec::new() };
+    check_version(&mut checker);
+
+    let schema = DottyConfig::json_schema();
+    check_node(&mut checker, &root, &schema, &schema, &KeyPath::default());
+    let structurally_valid = !checker.diagnostics.iter().any(|d| d.severity == Severity::Error);
+    check_semantics(&mut checker, repo_path);
+
+    // The schema walk is lenient about scalar types, let serde catch the rest
+    if structurally_valid {
+        if let Err(e) = format::deserialize::<DottyConfig>(format, source) {
+            checker.diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: e.message,
+                position: e.position,
+            });
+        }
+    }
+
+    checker.diagnostics.sort_by_key(|d| d.position.map(|p| (p.line, p.column)));
+    checker.diagnostics
+}
+
+fn check_version(checker: &mut Checker) {
+    let Some(node) = checker.root.get("version") else {
+        checker.report_at(
+            Severity::Warning,
+            checker.root.position,
+            format!(
+                "no `version` key, run `dotty migrate` to upgrade to format version {}",
+                migrate::CURRENT_VERSION
+            ),
+        );
+        return;
+    };
+
+    // Non-numeric versions are reported by the schema walk
+    let Some(version) = node.as_str().and_then(|v| v.parse::<u64>().ok()) else {
+        return;
+    };
+
+    if version > migrate::CURRENT_VERSION {
+        checker.report_at(
+            Severity::Error,
+            node.position,
+            format!(
+                "format version {} is newer than the supported version {}, please update dotty",
+                version,
+                migrate::CURRENT_VERSION
+            ),
+        );
+    } else if version < migrate::CURRENT_VERSION {
+        checker.report_at(
+            Severity::Warning,
+            node.position,
+            format!(
+                "format version {} is outdated, run `dotty migrate` to upgrade to version {}",
+                version,
+                migrate::CURRENT_VERSION
+            ),
+        );
+    }
+}
+
+fn resolve_ref<'s>(schema: &'s Schema, root: &'s Schema) -> &'s Schema {
+    let target = schema
+        .get("$ref")
+        .and_then(Schema::as_str)
+        .and_then(|r| r.strip_prefix("#/"))
+        .map(|pointer| root.pointer(&format!("/{}", pointer)));
+
+    match target {
+        Some(Some(resolved)) => resolve_ref(resolved, root),
+        _ => schema,
+    }
+}
+
+fn allowed_types(schema: &Schema) -> Option<Vec<&str>> {
+    match schema.get("type")? {
+        Schema::String(t) => Some(vec![t.as_str()]),
+        Schema::Array(types) => Some(types.iter().filter_map(Schema::as_str).collect()),
+        _ => None,
+    }
+}
+
+fn matches_type(node: &Node, schema: &Schema, root: &Schema) -> bool {
+    let schema = resolve_ref(schema, root);
+    match allowed_types(schema) {
+        Some(types) => node.json_types().iter().any(|t| types.contains(t)),
+        None => true,
+    }
+}
+
+/// Walks the document alongside the JSON Schema, reporting structural problems
+fn check_node(checker: &mut Checker, node: &Node, schema: &Schema, root: &Schema, path: &KeyPath) {
+    let schema = resolve_ref(schema, root);
+
+    if let Some(variants) = schema.get("anyOf").or_else(|| schema.get("oneOf")).and_then(Schema::as_array) {
+        // Mappings may match several variants by type, such as externally tagged enums,
+        // so the one whose required keys are present is preferred
+        let matching: Vec<&Schema> = variants.iter().filter(|variant| matches_type(node, variant, root)).collect();
+        let variant = matching.iter().find(|variant| has_required_keys(node, variant, root)).or(matching.first());
+        match variant {
+            Some(variant) => check_node(checker, node, variant, root, path),
+            None => checker.report_at(
+                Severity::Error,
+                node.position,
+                format!("{} has an invalid type", path),
+            ),
+        }
+        return;
+    }
+
+    if matches!(node.value, Value::Alias) {
+        return;
+    }
+
+    if let Some(types) = allowed_types(schema) {
+        if !node.json_types().iter().any(|t| types.contains(t)) {
+            checker.report_at(
+                Severity::Error,
+                node.position,
+                format!("{} must be {}", path, describe_types(&types)),
+            );
+            return;
+        }
+    }
+
+    if let (Some(allowed), Some(text)) = (schema.get("enum").and_then(Schema::as_array), node.as_str()) {
+        if !allowed.iter().any(|v| v.as_str() == Some(text)) {
+            let names: Vec<_> = allowed.iter().filter_map(Schema::as_str).collect();
+            checker.report_at(
+                Severity::Error,
+                node.position,
+                format!("{} must be one of: {}", path, names.join(", ")),
+            );
+        }
+    }
+
+    match &node.value {
+        Value::Mapping(entries) => check_mapping(checker, node, entries, schema, root, path),
+        Value::Sequence(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_node(checker, item, item_schema, root, &path.index(i));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_required_keys(node: &Node, schema: &Schema, root: &Schema) -> bool {
+    resolve_ref(schema, root)
+        .get("required")
+        .and_then(Schema::as_array)
+        .is_some_and(|required| required.iter().filter_map(Schema::as_str).all(|key| node.get(key).is_some()))
+}
+
+fn check_mapping(
+    checker: &mut Checker,
+    node: &Node,
+    entries: &[(Node, Node)],
+    schema: &Schema,
+    root: &Schema,
+    path: &KeyPath,
+) {
+    let properties = schema.get("properties").and_then(Schema::as_object);
+    let closed = schema.get("additionalProperties") == Some(&Schema::Bool(false));
+    let mut seen = HashMap::new();
+
+    for (key, value) in entries {
+        let Some(name) = key.as_str() else {
+            checker.report_at(Severity::Error, key.position, format!("{} has a non-string key", path));
+            continue;
+        };
+
+        if let Some(first) = seen.insert(name, key.position) {
+            checker.report_at(
+                Severity::Error,
+                key.position,
+                format!("duplicate key `{}` (first defined on line {})", name, first.line),
+            );
+        }
+
+        match properties.and_then(|p| p.get(name)) {
+            Some(property) => check_node(checker, value, property, root, &path.key(name)),
+            None if closed => {
+                let mut message = format!("unknown key `{}` in {}", name, path);
+                if let Some(suggestion) = properties.and_then(|p| closest_match(name, p.keys())) {
+                    message.push_str(&format!(" (did you mean `{}`?)", suggestion));
+                }
+                checker.report_at(Severity::Error, key.position, message);
+            }
+            None => {
+                if let Some(additional) = schema.get("additionalProperties").filter(|s| s.is_object()) {
+                    check_node(checker, value, additional, root, &path.key(name));
+                }
+            }
+        }
+    }
+
+    if let Some(required) = schema.get("required").and_then(Schema::as_array) {
+        for key in required.iter().filter_map(Schema::as_str) {
+            if !seen.contains_key(key) {
+                checker.report_at(
+                    Severity::Error,
+                    node.position,
+                    format!("missing required key `{}` in {}", key, path),
+                );
+            }
+        }
+    }
+}
+
+fn describe_types(types: &[&str]) -> String {
+    let names: Vec<_> = types
+        .iter()
+        .filter(|t| **t != "null")
+        .map(|t| match *t {
+            "object" => "a mapping",
+            "array" => "a list",
+            "string" => "a string",
+            "boolean" => "true or false",
+            "integer" => "an integer",
+            "number" => "a number",
+            other => other,
+        })
+        .collect();
+    names.join(" or ")
+}
+
+/// Returns the candidate closest to `name`, if any is close enough to be a likely typo
+pub fn closest_match<'a, I, S>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a S>,
+    S: AsRef<str> + ?Sized + 'a,
+{
+    candidates
+        .into_iter()
+        .map(|c| (c.as_ref(), strsim::jaro_winkler(name, c.as_ref())))
+        .filter(|(_, score)| *score > 0.8)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(candidate, _)| candidate)
+}
+
+/// Reports combinations of `group`, `provider`, `alternatives`, `source` and `is_aur` that cannot be resolved
+fn check_package_kind(checker: &mut Checker, package: &Node, package_path: &KeyPath, name: &str) {
+    let is_set = |key: &str| match package.get(key).map(|n| &n.value) {
+        Some(Value::Scalar { text, .. }) => text != "false",
+        Some(Value::Sequence(items)) => !items.is_empty(),
+        _ => false,
+    };
+    let source = package.get("source").and_then(Node::as_str);
+
+    if is_set("is_aur") && source.is_some_and(|s| s != "aur") {
+        checker.report(
+            Severity::Error,
+            &package_path.key("is_aur"),
+            format!("{} sets `is_aur` but its source is {}", name, source.unwrap_or_default()),
+        );
+    }
+
+    if let Some(version) = package.get("version").and_then(Node::as_str) {
+        if let Err(e) = VersionReq::parse(version) {
+            checker.report(Severity::Error, &package_path.key("version"), format!("{}: {}", name, e));
+        }
+    }
+
+    if is_set("group") {
+        for key in ["provider", "alternatives", "is_aur", "version"] {
+            if is_set(key) {
+                checker.report(
+                    Severity::Error,
+                    &package_path.key(key),
+                    format!("`{}` cannot be used on {}, which is a package group", key, name),
+                );
+            }
+        }
+        if source.is_some_and(|s| s != "pacman") {
+            checker.report(
+                Severity::Error,
+                &package_path.key("source"),
+                format!("{} is a package group, which only pacman supports", name),
+            );
+        }
+    } else if is_set("provider") && is_set("alternatives") {
+        checker.report(
+            Severity::Error,
+            &package_path.key("alternatives"),
+            format!("{} sets both `provider` and `alternatives`, use only one", name),
+        );
+    }
+}
+
+/// Reports services that do nothing or are both masked and started
+fn check_services(checker: &mut Checker, package: &Node, package_path: &KeyPath, name: &str) {
+    let Some(Value::Sequence(services)) = package.get("services").map(|s| &s.value) else {
+        return;
+    };
+
+    for (i, service) in services.iter().enumerate() {
+        let service_path = package_path.key("services").index(i);
+        let unit = service.get("unit").and_then(Node::as_str).unwrap_or_default();
+        let is_set = |key: &str| service.get(key).and_then(Node::as_str) == Some("true");
+
+        if !is_set("enable") && !is_set("start") && !is_set("mask") {
+            checker.report(
+                Severity::Warning,
+                &service_path,
+                format!("service `{}` of {} sets none of `enable`, `start` or `mask`", unit, name),
+            );
+        } else if is_set("mask") && (is_set("enable") || is_set("start")) {
+            checker.report(
+                Severity::Error,
+                &service_path.key("mask"),
+                format!("service `{}` of {} cannot be masked and enabled or started", unit, name),
+            );
+        }
+    }
+}
+
+/// Semantic checks that need the repository on disk.
+///
+/// These run on the document tree rather than the deserialized config so
+/// they still report problems when other parts of the file are malformed.
+fn check_semantics(checker: &mut Checker, repo_path: &Path) {
+    let root = checker.root;
+    let mut destinations: Vec<(PathBuf, KeyPath, String)> = Vec::new();
+    let packages = match root.get("packages").map(|p| &p.value) {
+        Some(Value::Sequence(packages)) => packages,
+        _ => return,
+    };
+    let secrets: Vec<&str> = match root.get("secrets").map(|s| &s.value) {
+        Some(Value::Mapping(entries)) => entries.iter().filter_map(|(key, _)| key.as_str()).collect(),
+        _ => Vec::new(),
+    };
+
+    for (i, package) in packages.iter().enumerate() {
+        let package_path = KeyPath::default().key("packages").index(i);
+        let mut name = package.get("name").and_then(Node::as_str).unwrap_or_default().to_string();
+
+        if name.trim().is_empty() {
+            if package.get("name").is_some() {
+                checker.report(Severity::Error, &package_path.key("name"), "package name cannot be empty".to_string());
+            }
+            name = package_path.to_string();
+        }
+
+        check_package_kind(checker, package, &package_path, &name);
+        check_services(checker, package, &package_path, &name);
+
+        let files = match package.get("files").map(|f| &f.value) {
+            Some(Value::Sequence(files)) => files,
+            _ => continue,
+        };
+
+        for (j, file) in files.iter().enumerate() {
+            let file_path = package_path.key("files").index(j);
+
+            if let Some(source) = file.get("source") {
+                let source = source.as_str().unwrap_or_default();
+                if source.trim().is_empty() {
+                    checker.report(
+                        Severity::Error,
+                        &file_path.key("source"),
+                        format!("source path cannot be empty for package {}", name),
+                    );
+                } else if !repo_path.join(source.trim_start_matches('/')).exists() {
+                    checker.report(
+                        Severity::Error,
+                        &file_path.key("source"),
+                        format!("source `{}` does not exist in the repository", source),
+                    );
+                } else if repo_path.join(source.trim_start_matches('/')).is_dir() {
+                    for key in ["encrypted", "template"] {
+                        if file.get(key).and_then(Node::as_str) == Some("true") {
+                            checker.report(
+                                Severity::Error,
+                                &file_path.key(key),
+                                format!("source `{}` is a directory, `{}` only applies to single files", source, key),
+                            );
+                        }
+                    }
+                } else if file.get("template").and_then(Node::as_str) == Some("true")
+                    && file.get("encrypted").and_then(Node::as_str) != Some("true")
+                {
+                    // Encrypted templates are only checked when they are rendered
+                    let template = std::fs::read_to_string(repo_path.join(source.trim_start_matches('/'))).unwrap_or_default();
+                    for secret in referenced_secrets(&template).into_iter().filter(|s| !secrets.contains(s)) {
+                        checker.report(
+                            Severity::Error,
+                            &file_path.key("template"),
+                            format!("template `{}` uses secret `{}`, which is not defined in `secrets`", source, secret),
+                        );
+                    }
+                }
+            }
+
+            let Some(destination) = file.get("destination") else {
+                continue;
+            };
+            let destination = destination.as_str().unwrap_or_default();
+            if destination.trim().is_empty() {
+                checker.report(
+                    Severity::Error,
+                    &file_path.key("destination"),
+                    format!("destination path cannot be empty for package {}", name),
+                );
+                continue;
+            }
+
+            let destination = PathBuf::from(shellexpand::tilde(destination).into_owned());
+            destinations.push((destination, file_path.key("destination"), name.clone()));
+        }
+    }
+
+    for (i, (destination, path, package)) in destinations.iter().enumerate() {
+        for (other, other_path, other_package) in &destinations[..i] {
+            let line = other_path
+                .resolve(root)
+                .map(|node| node.position.line)
+                .unwrap_or_default();
+
+            if destination == other {
+                checker.report(
+                    Severity::Error,
+                    path,
+                    format!(
+                        "destination `{}` of {} is also used by {} on line {}",
+                        destination.display(), package, other_package, line
+                    ),
+                );
+            } else if destination.starts_with(other) || other.starts_with(destination) {
+                checker.report(
+                    Severity::Warning,
+                    path,
+                    format!(
+                        "destination `{}` of {} overlaps `{}` of {} on line {}",
+                        destination.display(), package, other.display(), other_package, line
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(source: &str, repo: &Path) -> Vec<Diagnostic> {
+        check_source(source, ConfigFormat::Yaml, repo)
+    }
+
+    fn at(diagnostics: &[Diagnostic], needle: &str) -> Option<(Severity, usize, usize)> {
+        diagnostics
+            .iter()
+            .find(|d| d.message.contains(needle))
+            .map(|d| (d.severity, d.position.map_or(0, |p| p.line), d.position.map_or(0, |p| p.column)))
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_their_position() {
+        let repo = tempfile::tempdir().unwrap();
+        let diagnostics = check("version: 1\npackages: [\n", repo.path());
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.starts_with("syntax error"));
+        assert!(diagnostics[0].position.is_some_and(|p| p.line >= 2));
+    }
+
+    #[test]
+    fn points_unknown_keys_at_the_key() {
+        let repo = tempfile::tempdir().unwrap();
+        let diagnostics = check("version: 1\npackages:\n  - name: git\n    sorce: x\n", repo.path());
+        let (severity, line, column) = at(&diagnostics, "unknown key `sorce`").unwrap();
+        assert_eq!((severity, line, column), (Severity::Error, 4, 5));
+        assert!(diagnostics.iter().any(|d| d.message.contains("did you mean `source`?")));
+    }
+
+    #[test]
+    fn reports_duplicate_keys_at_the_second_one() {
+        let repo = tempfile::tempdir().unwrap();
+        let diagnostics = check("version: 1\npackages:\n  - name: git\n    name: vim\n", repo.path());
+        let (severity, line, column) = at(&diagnostics, "duplicate key `name` (first defined on line 3)").unwrap();
+        assert_eq!((severity, line, column), (Severity::Error, 4, 5));
+    }
+
+    #[test]
+    fn reports_missing_required_keys_at_the_mapping() {
+        let repo = tempfile::tempdir().unwrap();
+        let diagnostics = check("version: 1\npackages:\n  - files: []\n", repo.path());
+        let (severity, line, _) = at(&diagnostics, "missing required key `name` in packages[0]").unwrap();
+        assert_eq!((severity, line), (Severity::Error, 3));
+    }
+
+    #[test]
+    fn warns_about_a_missing_version() {
+        let repo = tempfile::tempdir().unwrap();
+        let diagnostics = check("packages: []\n", repo.path());
+        let (severity, line, _) = at(&diagnostics, "no `version` key").unwrap();
+        assert_eq!((severity, line), (Severity::Warning, 1));
+    }
+
+    #[test]
+    fn reports_missing_sources() {
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::create_dir(repo.path().join("nvim")).unwrap();
+        let config = "version: 1\npackages:\n  - name: neovim\n    files:\n      - source: nvim\n        destination: ~/.config/nvim\n      - source: missing\n        destination: ~/.config/missing\n";
+        let diagnostics = check(config, repo.path());
+        assert_eq!(at(&diagnostics, "`missing` does not exist"), Some((Severity::Error, 7, 17)));
+        assert!(at(&diagnostics, "`nvim` does not exist").is_none());
+    }
+
+    #[test]
+    fn reports_duplicate_and_overlapping_destinations() {
+        let repo = tempfile::tempdir().unwrap();
+        for dir in ["a", "b", "c"] {
+            std::fs::create_dir(repo.path().join(dir)).unwrap();
+        }
+        let config = "version: 1
+packages:
+  - name: one
+    files:
+      - source: a
+        destination: /tmp/dotty/config
+  - name: two
+    files:
+      - source: b
+        destination: /tmp/dotty/config
+      - source: c
+        destination: /tmp/dotty/config/sub
+";
+        let diagnostics = check(config, repo.path());
+        assert_eq!(
+            at(&diagnostics, "is also used by one on line 6"),
+            Some((Severity::Error, 10, 22))
+        );
+        assert_eq!(
+            at(&diagnostics, "`/tmp/dotty/config/sub` of two overlaps `/tmp/dotty/config` of one on line 6"),
+            Some((Severity::Warning, 12, 22))
+        );
+    }
+
+    #[test]
+    fn sorts_diagnostics_by_position() {
+        let repo = tempfile::tempdir().unwrap();
+        let diagnostics = check("packages:\n  - name: git\n    bogus: 1\n  - files: []\n", repo.path());
+        let lines: Vec<_> = diagnostics.iter().filter_map(|d| d.position).map(|p| p.line).collect();
+        let mut sorted = lines.clone();
+        sorted.sort();
+        assert_eq!(lines, sorted);
+        assert!(lines.len() >= 3);
+    }
+}
@@ -1,73 +1,485 @@
 use anyhow::{Context, Result};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::core::{format::{self, ConfigFormat}, migrate, version::VersionReq};
+use crate::utils::copy::CopyOptions;
 
-#[derive(Serialize, Deserialize)]
+/// Unix permission bits written as an octal string such as `"0600"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FileMode(pub u32);
+
+impl TryFrom<String> for FileMode {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let digits = value.trim_start_matches("0o");
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if (3..=4).contains(&digits.len()) => Ok(Self(mode)),
+            _ => Err(format!("invalid mode `{}`, expected octal permissions like \"0644\"", value)),
+        }
+    }
+}
+
+impl From<FileMode> for String {
+    fn from(mode: FileMode) -> Self {
+        mode.to_string()
+    }
+}
+
+impl fmt::Display for FileMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+impl JsonSchema for FileMode {
+    fn schema_name() -> Cow<'static, str> {
+        "FileMode".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "Octal permission bits, e.g. \"0644\"",
+            "type": "string",
+            "pattern": "^(0o)?[0-7]{3,4}$"
+        })
+    }
+}
+
+/// A file or directory copied from the repository to the system
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct FileMapping {
+    /// Path relative to the repository root
     pub source: String,
+    /// Target path on the system, `~` is expanded to the home directory
     pub destination: String,
+    /// Permissions of the deployed file, for directories of every file inside
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<FileMode>,
+    /// Permissions of every directory inside a deployed directory, including itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_mode: Option<FileMode>,
+    /// User that should own the deployed files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Group that should own the deployed files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// The source is encrypted with age or gpg and decrypted when deployed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+    /// The source is a template, `{{ secrets.NAME }}` is replaced by the secret's value
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub template: bool,
+}
+
+impl FileMapping {
+    pub fn source_path(&self, repo_path: &Path) -> PathBuf {
+        repo_path.join(self.source.trim_start_matches('/'))
+    }
+
+    pub fn destination_path(&self) -> PathBuf {
+        PathBuf::from(shellexpand::tilde(&self.destination).into_owned())
+    }
+
+    /// Whether the deployed contents differ from the source in the repository
+    pub fn is_generated(&self) -> bool {
+        self.encrypted || self.template
+    }
+
+    /// Returns true if the mapping declares any permission or ownership settings
+    pub fn has_attributes(&self) -> bool {
+        self.mode.is_some() || self.dir_mode.is_some() || self.owner.is_some() || self.group.is_some()
+    }
+}
+
+/// Distribution families with a supported system package manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Distro {
+    /// Arch Linux and derivatives, using pacman
+    Arch,
+    /// Debian, Ubuntu and derivatives, using apt
+    Debian,
+    /// Fedora and RHEL based distributions, using dnf
+    Fedora,
+}
+
+impl Distro {
+    /// Package manager that installs packages without an explicit source
+    pub fn system_source(&self) -> Source {
+        match self {
+            Distro::Arch => Source::Pacman,
+            Distro::Debian => Source::Apt,
+            Distro::Fedora => Source::Dnf,
+        }
+    }
+}
+
+impl fmt::Display for Distro {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Distro::Arch => write!(f, "Arch Linux"),
+            Distro::Debian => write!(f, "Debian"),
+            Distro::Fedora => write!(f, "Fedora"),
+        }
+    }
+}
+
+/// Package manager a package is installed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// Official repositories through pacman
+    Pacman,
+    /// Arch User Repository through an AUR helper
+    Aur,
+    /// Debian and Ubuntu repositories through apt
+    Apt,
+    /// Fedora repositories through dnf
+    Dnf,
+    /// Flatpak applications, by application ID
+    Flatpak,
+    /// Crates installed with `cargo install`
+    Cargo,
+    /// Python applications installed with pipx
+    Pipx,
+    /// Global npm packages
+    Npm,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Pacman => write!(f, "pacman"),
+            Source::Aur => write!(f, "AUR"),
+            Source::Apt => write!(f, "apt"),
+            Source::Dnf => write!(f, "dnf"),
+            Source::Flatpak => write!(f, "flatpak"),
+            Source::Cargo => write!(f, "cargo"),
+            Source::Pipx => write!(f, "pipx"),
+            Source::Npm => write!(f, "npm"),
+        }
+    }
+}
+
+impl Source {
+    /// Whether this source can be used on `distro`, `None` meaning an unknown distribution
+    pub fn is_supported_on(&self, distro: Option<Distro>) -> bool {
+        match self {
+            Source::Pacman | Source::Aur => distro == Some(Distro::Arch),
+            Source::Apt => distro == Some(Distro::Debian),
+            Source::Dnf => distro == Some(Distro::Fedora),
+            Source::Flatpak | Source::Cargo | Source::Pipx | Source::Npm => true,
+        }
+    }
+}
+
+/// Package names that differ between distributions
+#[derive(Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PackageNames {
+    /// Name on Arch Linux and derivatives
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// Name on Debian, Ubuntu and derivatives
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debian: Option<String>,
+    /// Name on Fedora and RHEL based distributions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fedora: Option<String>,
+}
+
+impl PackageNames {
+    pub fn get(&self, distro: Distro) -> Option<&str> {
+        match distro {
+            Distro::Arch => self.arch.as_deref(),
+            Distro::Debian => self.debian.as_deref(),
+            Distro::Fedora => self.fedora.as_deref(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.arch.is_none() && self.debian.is_none() && self.fedora.is_none()
+    }
+}
+
+/// Which systemd instance manages a service
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceScope {
+    /// The user's own instance, `systemctl --user`
+    #[default]
+    User,
+    /// The system instance, changed with sudo
+    System,
+}
+
+impl fmt::Display for ServiceScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServiceScope::User => write!(f, "user"),
+            ServiceScope::System => write!(f, "system"),
+        }
+    }
+}
+
+/// A systemd unit set up after its package is installed
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Service {
+    /// Unit name such as `syncthing.service`
+    pub unit: String,
+    /// Systemd instance the unit belongs to
+    #[serde(default)]
+    pub scope: ServiceScope,
+    /// Start the unit at boot or login
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub enable: bool,
+    /// Start the unit right away
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub start: bool,
+    /// Keep the unit from being started at all
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mask: bool,
+}
+
+/// Shell commands run at fixed points of an installation
+#[derive(Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Run before packages are installed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_install: Vec<String>,
+    /// Run after packages are installed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_install: Vec<String>,
+    /// Run after files are copied
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_files: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre_install.is_empty() && self.post_install.is_empty() && self.post_files.is_empty()
+    }
+}
+
+/// A package to install along with its configuration files
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Package {
+    /// Package name as known to its package manager
     pub name: String,
-    #[serde(default)]
+    /// Per-distribution names, used instead of `name` on that distribution
+    #[serde(default, skip_serializing_if = "PackageNames::is_empty")]
+    pub names: PackageNames,
+    /// Package manager to install with, defaults to the distribution's own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+    /// Shorthand for `source: aur`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub is_aur: bool,
-    #[serde(default)]
+    /// The name is a package group, all of its members are installed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub group: bool,
+    /// Package to install for a virtual package such as `jack`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Packages that can stand in for this one, used in order when it is unavailable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<String>,
+    /// Required version such as `>=0.10` or `>=0.10, <0.11`, a plain version is installed exactly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Files and directories deployed for this package
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub files: Vec<FileMapping>,
+    /// Commands run around installing this package and copying its files
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// Systemd units to enable, start or mask once the package is installed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<Service>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Package {
+    /// The parsed `version` constraint
+    pub fn version_req(&self) -> Result<Option<VersionReq>> {
+        self.version
+            .as_deref()
+            .map(|version| VersionReq::parse(version).with_context(|| format!("Invalid version of {}", self.name)))
+            .transpose()
+    }
+
+    /// The package manager this package is installed with on `distro`.
+    ///
+    /// Returns `None` for system packages on an unknown distribution.
+    pub fn source(&self, distro: Option<Distro>) -> Option<Source> {
+        match self.source {
+            Some(source) => Some(source),
+            None if self.is_aur => Some(Source::Aur),
+            None => distro.map(|d| d.system_source()),
+        }
+    }
+
+    /// The package name on `distro`
+    pub fn name_for(&self, distro: Option<Distro>) -> &str {
+        distro
+            .and_then(|d| self.names.get(d))
+            .unwrap_or(&self.name)
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Settings {
+    /// Skip files that already exist at the destination
     #[serde(default)]
     pub skip_existing: bool,
+    /// Keep the modification times of copied files
+    #[serde(default)]
+    pub preserve_times: bool,
+    /// Copy extended attributes along with file contents
+    #[serde(default)]
+    pub preserve_xattrs: bool,
+    /// Show the PKGBUILD of every AUR package and ask before building it
+    #[serde(default)]
+    pub review_pkgbuilds: bool,
+    /// What to do when an installed package does not match its `version`
+    #[serde(default)]
+    pub version_mismatch: VersionMismatch,
+    /// Directory searched first for packages of a pinned version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_cache: Option<String>,
+    /// Arch Linux Archive mirror for pinned versions, a URL or a local directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_mirror: Option<String>,
+    /// age identity or gpg secret key that decrypts `encrypted` sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_file: Option<String>,
+}
+
+/// Reaction to a package that does not satisfy its version constraint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionMismatch {
+    /// Report the mismatch and continue
+    #[default]
+    Warn,
+    /// Report the mismatch and exit with an error
+    Fail,
+}
+
+/// Where the value of a secret comes from
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum SecretSource {
+    Env {
+        /// Environment variable holding the value
+        env: String,
+    },
+    File {
+        /// File containing the value, `~` is expanded
+        file: String,
+    },
+    Pass {
+        /// Entry of the `pass` password store, its first line is the value
+        pass: String,
+    },
+    Command {
+        /// Shell command printing the value
+        command: String,
+    },
 }
 
-#[derive(Serialize, Deserialize)]
+/// Settings of the machine and the current user, applied with sudo where needed
+#[derive(Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SystemSettings {
+    /// Login shell of the current user, such as `/usr/bin/zsh`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// Groups the current user is added to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// System locale such as `en_US.UTF-8`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Timezone such as `Europe/Berlin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+impl SystemSettings {
+    pub fn is_empty(&self) -> bool {
+        self.shell.is_none() && self.groups.is_empty() && self.locale.is_none() && self.timezone.is_none()
+    }
+}
+
+/// Configuration read from `dotty.yaml` (or its TOML and JSON equivalents)
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct DottyConfig {
+    /// Configuration format version, see `dotty migrate`
     #[serde(default)]
-    pub packages: Vec<Package>,
+    pub version: u64,
     #[serde(default)]
+    pub packages: Vec<Package>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub settings: Option<Settings>,
+    /// Commands run around the whole installation
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// Login shell, groups, locale and timezone
+    #[serde(default, skip_serializing_if = "SystemSettings::is_empty")]
+    pub system: SystemSettings,
+    /// Values filled into templated files, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub secrets: BTreeMap<String, SecretSource>,
 }
 
 impl DottyConfig {
+    /// Loads the configuration from `dotty.yaml`, `dotty.yml`, `dotty.toml`
+    /// or `dotty.json` in `path`, upgrading older format versions in memory
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let config_path = path.as_ref().join("dotty.yaml");
-        
-        if !config_path.exists() {
-            anyhow::bail!("dotty.yaml not found in repository");
-        }
+        let config_path = format::find_config(path.as_ref())?;
+        Self::from_file(&config_path)
+    }
 
-        let contents = std::fs::read_to_string(&config_path)
+    pub fn from_file(config_path: &Path) -> Result<Self> {
+        let format = ConfigFormat::from_path(config_path)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported configuration file: {}", config_path.display()))?;
+
+        let contents = std::fs::read_to_string(config_path)
             .with_context(|| format!("Failed to read {}", config_path.display()))?;
 
-        serde_yaml::from_str(&contents)
-            .with_context(|| format!("Failed to parse {} as YAML", config_path.display()))
-    }
-
-    pub fn validate(&self) -> Result<()> {
-        // Validate packages if any exist
-        if !self.packages.is_empty() {
-            for package in &self.packages {
-                if package.name.is_empty() {
-                    anyhow::bail!("Package name cannot be empty");
-                }
-                
-                if !package.files.is_empty() {
-                    for file in &package.files {
-                        if file.source.is_empty() {
-                            anyhow::bail!("Source path cannot be empty for package {}", package.name);
-                        }
-                        if file.destination.is_empty() {
-                            anyhow::bail!("Destination path cannot be empty for package {}", package.name);
-                        }
-                    }
-                }
-            }
+        let value: serde_yaml::Value = format::deserialize(format, &contents)
+            .map_err(|e| anyhow::anyhow!("{}: {}", config_path.display(), e))?;
+
+        let version = migrate::detect_version(&value)
+            .map_err(|e| anyhow::anyhow!("{}: {}", config_path.display(), e))?;
+
+        // Deserialize the source directly when possible to keep error positions
+        if version == migrate::CURRENT_VERSION {
+            return format::deserialize(format, &contents)
+                .map_err(|e| anyhow::anyhow!("{}: {}", config_path.display(), e));
         }
 
-        Ok(())
+        let upgraded = migrate::upgrade_value(value)
+            .map_err(|e| anyhow::anyhow!("{}: {}", config_path.display(), e))?;
+        serde_yaml::from_value(upgraded)
+            .map_err(|e| anyhow::anyhow!("{}: {}", config_path.display(), e))
+    }
+
+    /// Returns the JSON Schema describing `dotty.yaml`
+    pub fn json_schema() -> serde_json::Value {
+        schemars::schema_for!(DottyConfig).to_value()
     }
 
     pub fn get_all_file_mappings(&self) -> Vec<&FileMapping> {
@@ -78,6 +490,39 @@ impl DottyConfig {
     }
 
     pub fn should_skip_existing(&self) -> bool {
-        self.settings.as_ref().map_or(false, |s| s.skip_existing)
+        self.settings.as_ref().is_some_and(|s| s.skip_existing)
     }
-} 
\ No newline at end of file
+
+    pub fn should_review_pkgbuilds(&self) -> bool {
+        self.settings.as_ref().is_some_and(|s| s.review_pkgbuilds)
+    }
+
+    pub fn version_mismatch(&self) -> VersionMismatch {
+        self.settings.as_ref().map(|s| s.version_mismatch).unwrap_or_default()
+    }
+
+    /// Where pinned packages are looked for before the archive, pacman's cache by default
+    pub fn package_cache(&self) -> PathBuf {
+        let cache = self.settings.as_ref().and_then(|s| s.package_cache.as_deref());
+        PathBuf::from(shellexpand::tilde(cache.unwrap_or("/var/cache/pacman/pkg")).into_owned())
+    }
+
+    pub fn archive_mirror(&self) -> String {
+        let mirror = self.settings.as_ref().and_then(|s| s.archive_mirror.as_deref());
+        shellexpand::tilde(mirror.unwrap_or("https://archive.archlinux.org")).into_owned()
+    }
+
+    /// Key for encrypted sources, gpg falls back to the user's keyring without one
+    pub fn identity_file(&self) -> Option<PathBuf> {
+        let identity = self.settings.as_ref().and_then(|s| s.identity_file.as_deref())?;
+        Some(PathBuf::from(shellexpand::tilde(identity).into_owned()))
+    }
+
+    pub fn copy_options(&self) -> CopyOptions {
+        CopyOptions {
+            skip_existing: self.should_skip_existing(),
+            preserve_times: self.settings.as_ref().is_some_and(|s| s.preserve_times),
+            preserve_xattrs: self.settings.as_ref().is_some_and(|s| s.preserve_xattrs),
+        }
+    }
+}
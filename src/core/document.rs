@@ -0,0 +1,267 @@
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+use crate::core::format::{self, offset_to_position, ConfigFormat};
+
+/// A 1-based line/column position inside a configuration file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A parse error that happened before a document tree could be built
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub message: String,
+    pub position: Position,
+}
+
+#[derive(Debug)]
+pub enum Value {
+    Null,
+    Scalar { text: String, quoted: bool },
+    Sequence(Vec<Node>),
+    Mapping(Vec<(Node, Node)>),
+    Alias,
+}
+
+/// A configuration value annotated with the position it was read from
+#[derive(Debug)]
+pub struct Node {
+    pub value: Value,
+    pub position: Position,
+}
+
+impl Node {
+    /// Returns the value stored under `key` if this node is a mapping
+    pub fn get(&self, key: &str) -> Option<&Node> {
+        match &self.value {
+            Value::Mapping(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns the item at `index` if this node is a sequence
+    pub fn index(&self, index: usize) -> Option<&Node> {
+        match &self.value {
+            Value::Sequence(items) => items.get(index),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            Value::Scalar { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the JSON type names this node can be deserialized as
+    pub fn json_types(&self) -> &'static [&'static str] {
+        match &self.value {
+            Value::Null => &["null"],
+            Value::Sequence(_) => &["array"],
+            Value::Mapping(_) => &["object"],
+            Value::Alias => &[],
+            Value::Scalar { quoted: true, .. } => &["string"],
+            Value::Scalar { text, .. } => {
+                if matches!(text.as_str(), "true" | "false") {
+                    &["boolean", "string"]
+                } else if text.parse::<i64>().is_ok() {
+                    &["integer", "number", "string"]
+                } else if text.parse::<f64>().is_ok() {
+                    &["number", "string"]
+                } else {
+                    &["string"]
+                }
+            }
+        }
+    }
+}
+
+/// Parses configuration source in any supported format into a position-annotated tree
+pub fn parse(format: ConfigFormat, source: &str) -> Result<Node, SyntaxError> {
+    match format {
+        ConfigFormat::Yaml => parse_yaml(source),
+        ConfigFormat::Toml => parse_toml(source),
+        ConfigFormat::Json => {
+            // JSON is valid YAML, but serde_json gives more helpful syntax errors
+            if let Err(e) = format::deserialize::<serde_json::Value>(format, source) {
+                return Err(SyntaxError {
+                    message: e.message,
+                    position: e.position.unwrap_or(Position { line: 1, column: 1 }),
+                });
+            }
+            parse_yaml(source)
+        }
+    }
+}
+
+/// Parses YAML source into a position-annotated tree
+pub fn parse_yaml(source: &str) -> Result<Node, SyntaxError> {
+    let mut builder = TreeBuilder::default();
+    Parser::new_from_str(source)
+        .load(&mut builder, false)
+        .map_err(|e| SyntaxError {
+            message: e.info().to_string(),
+            position: to_position(e.marker()),
+        })?;
+
+    Ok(builder.root.unwrap_or(Node {
+        value: Value::Null,
+        position: Position { line: 1, column: 1 },
+    }))
+}
+
+/// Parses TOML source into a position-annotated tree
+pub fn parse_toml(source: &str) -> Result<Node, SyntaxError> {
+    let document = toml_edit::ImDocument::parse(source).map_err(|e| SyntaxError {
+        message: e.message().to_string(),
+        position: e
+            .span()
+            .map(|span| offset_to_position(source, span.start))
+            .unwrap_or(Position { line: 1, column: 1 }),
+    })?;
+
+    let start = Position { line: 1, column: 1 };
+    Ok(toml_table(source, document.as_table(), start))
+}
+
+fn toml_position(source: &str, span: Option<std::ops::Range<usize>>, fallback: Position) -> Position {
+    span.map_or(fallback, |span| offset_to_position(source, span.start))
+}
+
+fn toml_table(source: &str, table: &toml_edit::Table, position: Position) -> Node {
+    let position = toml_position(source, table.span(), position);
+    let entries = table
+        .iter()
+        .map(|(key, item)| {
+            let key_position = toml_position(source, table.key(key).and_then(|k| k.span()), position);
+            let key_node = Node {
+                value: Value::Scalar { text: key.to_string(), quoted: true },
+                position: key_position,
+            };
+            (key_node, toml_item(source, item, key_position))
+        })
+        .collect();
+
+    Node { value: Value::Mapping(entries), position }
+}
+
+fn toml_item(source: &str, item: &toml_edit::Item, position: Position) -> Node {
+    match item {
+        toml_edit::Item::None => Node { value: Value::Null, position },
+        toml_edit::Item::Value(value) => toml_value(source, value, position),
+        toml_edit::Item::Table(table) => toml_table(source, table, position),
+        toml_edit::Item::ArrayOfTables(tables) => Node {
+            value: Value::Sequence(
+                tables.iter().map(|table| toml_table(source, table, position)).collect(),
+            ),
+            position: toml_position(source, tables.span(), position),
+        },
+    }
+}
+
+fn toml_value(source: &str, value: &toml_edit::Value, position: Position) -> Node {
+    use toml_edit::Value as Toml;
+
+    let position = toml_position(source, value.span(), position);
+    let value = match value {
+        Toml::String(s) => Value::Scalar { text: s.value().clone(), quoted: true },
+        Toml::Integer(i) => Value::Scalar { text: i.value().to_string(), quoted: false },
+        Toml::Float(f) => Value::Scalar { text: f.value().to_string(), quoted: false },
+        Toml::Boolean(b) => Value::Scalar { text: b.value().to_string(), quoted: false },
+        Toml::Datetime(d) => Value::Scalar { text: d.value().to_string(), quoted: true },
+        Toml::Array(array) => Value::Sequence(
+            array.iter().map(|v| toml_value(source, v, position)).collect(),
+        ),
+        Toml::InlineTable(table) => Value::Mapping(
+            table
+                .iter()
+                .map(|(key, v)| {
+                    let key_position = toml_position(source, table.key(key).and_then(|k| k.span()), position);
+                    let key_node = Node {
+                        value: Value::Scalar { text: key.to_string(), quoted: true },
+                        position: key_position,
+                    };
+                    (key_node, toml_value(source, v, key_position))
+                })
+                .collect(),
+        ),
+    };
+
+    Node { value, position }
+}
+
+fn to_position(marker: &Marker) -> Position {
+    Position {
+        line: marker.line(),
+        column: marker.col() + 1,
+    }
+}
+
+enum Frame {
+    Sequence(Position, Vec<Node>),
+    Mapping(Position, Vec<(Node, Node)>, Option<Node>),
+}
+
+#[derive(Default)]
+struct TreeBuilder {
+    stack: Vec<Frame>,
+    root: Option<Node>,
+}
+
+impl TreeBuilder {
+    fn push(&mut self, node: Node) {
+        match self.stack.last_mut() {
+            Some(Frame::Sequence(_, items)) => items.push(node),
+            Some(Frame::Mapping(_, entries, pending_key)) => match pending_key.take() {
+                Some(key) => entries.push((key, node)),
+                None => *pending_key = Some(node),
+            },
+            None => {
+                if self.root.is_none() {
+                    self.root = Some(node);
+                }
+            }
+        }
+    }
+}
+
+impl MarkedEventReceiver for TreeBuilder {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        let position = to_position(&marker);
+        match event {
+            Event::Scalar(text, style, _, _) => {
+                let quoted = style != TScalarStyle::Plain;
+                let value = if !quoted && matches!(text.as_str(), "" | "~" | "null") {
+                    Value::Null
+                } else {
+                    Value::Scalar { text, quoted }
+                };
+                self.push(Node { value, position });
+            }
+            Event::Alias(_) => self.push(Node { value: Value::Alias, position }),
+            Event::SequenceStart(..) => self.stack.push(Frame::Sequence(position, Vec::new())),
+            Event::MappingStart(..) => self.stack.push(Frame::Mapping(position, Vec::new(), None)),
+            Event::SequenceEnd | Event::MappingEnd => {
+                let node = match self.stack.pop() {
+                    Some(Frame::Sequence(position, items)) => Node {
+                        value: Value::Sequence(items),
+                        position,
+                    },
+                    Some(Frame::Mapping(position, entries, _)) => Node {
+                        value: Value::Mapping(entries),
+                        position,
+                    },
+                    None => return,
+                };
+                self.push(node);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Serialize};
+use crate::core::document::Position;
+
+/// File names dotty looks for in a repository, in order of preference
+pub const CONFIG_FILE_NAMES: &[&str] = &["dotty.yaml", "dotty.yml", "dotty.toml", "dotty.json"];
+
+/// Serialization formats supported for the configuration file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Yaml => "yaml",
+            Self::Toml => "toml",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("dotty.{}", self.extension())
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Yaml => write!(f, "YAML"),
+            Self::Toml => write!(f, "TOML"),
+            Self::Json => write!(f, "JSON"),
+        }
+    }
+}
+
+/// Locates the configuration file in `dir`.
+///
+/// Having more than one of the supported files is an error, since it would
+/// be ambiguous which one dotty should use.
+pub fn find_config(dir: &Path) -> Result<PathBuf> {
+    let found: Vec<PathBuf> = CONFIG_FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|path| path.is_file())
+        .collect();
+
+    match found.as_slice() {
+        [] => anyhow::bail!(
+            "No configuration found in {} (expected one of: {})",
+            dir.display(),
+            CONFIG_FILE_NAMES.join(", ")
+        ),
+        [path] => Ok(path.clone()),
+        _ => {
+            let names: Vec<_> = found
+                .iter()
+                .filter_map(|p| p.file_name())
+                .map(|n| n.to_string_lossy())
+                .collect();
+            anyhow::bail!(
+                "Found multiple configuration files in {}: {}. Keep only one of them",
+                dir.display(),
+                names.join(", ")
+            )
+        }
+    }
+}
+
+/// A deserialization error with the position it happened at, if known
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub position: Option<Position>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some(p) => write!(f, "line {} column {}: {}", p.line, p.column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Converts a byte offset into a 1-based line and column
+pub fn offset_to_position(source: &str, offset: usize) -> Position {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+        line,
+        column: before[line_start..].chars().count() + 1,
+    }
+}
+
+/// Both serde_yaml and serde_json append the location to their messages,
+/// strip it since it is reported separately
+fn strip_location(message: String) -> String {
+    match message.find(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    }
+}
+
+/// Deserializes `source` written in `format`
+pub fn deserialize<T: DeserializeOwned>(format: ConfigFormat, source: &str) -> Result<T, ParseError> {
+    match format {
+        ConfigFormat::Yaml => serde_yaml::from_str(source).map_err(|e| ParseError {
+            position: e.location().map(|l| Position { line: l.line(), column: l.column() }),
+            message: strip_location(e.to_string()),
+        }),
+        ConfigFormat::Toml => toml::from_str(source).map_err(|e| ParseError {
+            position: e.span().map(|span| offset_to_position(source, span.start)),
+            message: e.message().to_string(),
+        }),
+        ConfigFormat::Json => serde_json::from_str(source).map_err(|e| ParseError {
+            position: Some(Position { line: e.line(), column: e.column().max(1) }),
+            message: strip_location(e.to_string()),
+        }),
+    }
+}
+
+/// Serializes `value` in `format`
+pub fn serialize<T: Serialize>(format: ConfigFormat, value: &T) -> Result<String> {
+    Ok(match format {
+        ConfigFormat::Yaml => serde_yaml::to_string(value)?,
+        ConfigFormat::Toml => toml::to_string_pretty(value)?,
+        ConfigFormat::Json => serde_json::to_string_pretty(value)? + "\n",
+    })
+}
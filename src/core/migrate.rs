@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use crate::core::format::{self, ConfigFormat};
+
+/// Version of the configuration format written by this release of dotty
+pub const CURRENT_VERSION: u64 = 1;
+
+/// An upgrade from one configuration format version to the next.
+///
+/// Every migration is implemented twice: once on the parsed value so older
+/// configs load in memory, and once on the source text so `dotty migrate`
+/// can rewrite the file without losing comments or formatting. JSON has no
+/// comments, so it is simply serialized again from the upgraded value.
+struct Migration {
+    from: u64,
+    description: &'static str,
+    value: fn(&mut Mapping),
+    text: fn(&str, ConfigFormat) -> String,
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "add the `version` key",
+    value: |_| {},
+    text: add_version_key,
+}];
+
+/// Returns the format version declared by a parsed config, `0` if it has none
+pub fn detect_version(value: &Value) -> Result<u64> {
+    match value.get("version") {
+        None => Ok(0),
+        Some(Value::Number(n)) => n
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("version must be a positive integer")),
+        Some(_) => anyhow::bail!("version must be a positive integer"),
+    }
+}
+
+fn ensure_supported(version: u64) -> Result<()> {
+    if version > CURRENT_VERSION {
+        anyhow::bail!(
+            "configuration uses format version {} but this dotty only supports up to {}, please update dotty",
+            version,
+            CURRENT_VERSION
+        );
+    }
+    Ok(())
+}
+
+/// Upgrades a parsed config in memory to the current format version
+pub fn upgrade_value(mut value: Value) -> Result<Value> {
+    let version = detect_version(&value)?;
+    ensure_supported(version)?;
+
+    // An empty file parses as null, treat it as a config without any keys
+    if value.is_null() {
+        value = Value::Mapping(Mapping::new());
+    }
+
+    if let Value::Mapping(mapping) = &mut value {
+        for migration in MIGRATIONS.iter().filter(|m| m.from >= version) {
+            (migration.value)(mapping);
+        }
+        // Keep `version` as the first key, that is where migrated files put it
+        mapping.remove("version");
+        let mut upgraded = Mapping::new();
+        upgraded.insert(Value::from("version"), Value::from(CURRENT_VERSION));
+        upgraded.extend(std::mem::take(mapping));
+        *mapping = upgraded;
+    }
+
+    Ok(value)
+}
+
+/// Describes the steps needed to bring `version` up to date
+pub fn pending_steps(version: u64) -> Vec<&'static str> {
+    MIGRATIONS
+        .iter()
+        .filter(|m| m.from >= version)
+        .map(|m| m.description)
+        .collect()
+}
+
+/// Result of rewriting a config file to the current format version
+pub struct SourceUpgrade {
+    pub from_version: u64,
+    pub source: String,
+}
+
+/// Rewrites configuration source to the current format version, keeping comments.
+///
+/// Returns `None` if the source is already up to date.
+pub fn upgrade_source(source: &str, format: ConfigFormat) -> Result<Option<SourceUpgrade>> {
+    let original: Value = format::deserialize(format, source)
+        .context("Failed to parse configuration")?;
+    let version = detect_version(&original)?;
+    ensure_supported(version)?;
+
+    if version == CURRENT_VERSION {
+        return Ok(None);
+    }
+
+    let expected = upgrade_value(original)?;
+    let rewritten = match format {
+        ConfigFormat::Json => format::serialize(format, &expected)?,
+        _ => MIGRATIONS
+            .iter()
+            .filter(|m| m.from >= version)
+            .fold(source.to_string(), |text, migration| (migration.text)(&text, format)),
+    };
+
+    // The text rewrite must produce exactly what the in-memory upgrade does
+    let actual: Value = format::deserialize(format, &rewritten)
+        .context("Migration produced an invalid configuration")?;
+    if actual != expected {
+        anyhow::bail!("Could not rewrite the configuration without changing its meaning");
+    }
+
+    Ok(Some(SourceUpgrade {
+        from_version: version,
+        source: rewritten,
+    }))
+}
+
+/// Inserts `version:` after the file's header comment.
+///
+/// Comments directly above the first key describe that key, so the version
+/// goes after the first blank line of the leading comments, or at the top.
+/// In YAML, the top is after any `%` directives and the `---` document marker.
+fn add_version_key(source: &str, format: ConfigFormat) -> String {
+    let mut lines: Vec<&str> = source.lines().collect();
+    let is_document_start = |line: &str| {
+        format == ConfigFormat::Yaml
+            && (line.starts_with('%')
+                || line.strip_prefix("---").is_some_and(|rest| rest.trim().is_empty() || rest.trim_start().starts_with('#')))
+    };
+    let first_key = lines
+        .iter()
+        .position(|line| {
+            let trimmed = line.trim();
+            !(trimmed.is_empty() || trimmed.starts_with('#') || is_document_start(line))
+        })
+        .unwrap_or(lines.len());
+
+    let start = lines[..first_key]
+        .iter()
+        .rposition(|line| is_document_start(line))
+        .map_or(0, |marker| marker + 1);
+    let insert_at = lines[start..first_key]
+        .iter()
+        .position(|line| line.trim().is_empty())
+        .map_or(start, |blank| start + blank + 1);
+
+    let version_line = match format {
+        ConfigFormat::Toml => format!("version = {}", CURRENT_VERSION),
+        _ => format!("version: {}", CURRENT_VERSION),
+    };
+    lines.insert(insert_at, "");
+    lines.insert(insert_at, &version_line);
+
+    let mut result = lines.join("\n");
+    if source.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_yaml(source: &str) -> String {
+        add_version_key(source, ConfigFormat::Yaml)
+    }
+
+    #[test]
+    fn adds_version_at_the_top_without_a_header() {
+        assert_eq!(add_yaml("packages: []\n"), "version: 1\n\npackages: []\n");
+    }
+
+    #[test]
+    fn keeps_comments_on_the_first_key_below_the_version() {
+        assert_eq!(
+            add_yaml("# my packages\npackages: []\n"),
+            "version: 1\n\n# my packages\npackages: []\n"
+        );
+    }
+
+    #[test]
+    fn adds_version_after_a_blank_line_separated_header() {
+        assert_eq!(
+            add_yaml("# dotfiles\n\n# my packages\npackages: []\n"),
+            "# dotfiles\n\nversion: 1\n\n# my packages\npackages: []\n"
+        );
+    }
+
+    #[test]
+    fn adds_version_after_the_document_start() {
+        assert_eq!(add_yaml("---\npackages: []\n"), "---\nversion: 1\n\npackages: []\n");
+        assert_eq!(
+            add_yaml("# dotfiles\n\n---\n# my packages\npackages: []\n"),
+            "# dotfiles\n\n---\nversion: 1\n\n# my packages\npackages: []\n"
+        );
+    }
+
+    #[test]
+    fn adds_version_after_directives() {
+        assert_eq!(
+            add_yaml("%YAML 1.2\n---\npackages: []\n"),
+            "%YAML 1.2\n---\nversion: 1\n\npackages: []\n"
+        );
+    }
+
+    #[test]
+    fn rewritten_documents_keep_their_meaning() {
+        for source in ["---\npackages: []\n", "%YAML 1.2\n---\n# header\n\npackages: []\n"] {
+            let upgrade = upgrade_source(source, ConfigFormat::Yaml).unwrap().unwrap();
+            assert_eq!(upgrade.from_version, 0);
+        }
+    }
+
+    #[test]
+    fn uses_toml_syntax_for_toml() {
+        assert_eq!(
+            add_version_key("[settings]\n", ConfigFormat::Toml),
+            "version = 1\n\n[settings]\n"
+        );
+    }
+}
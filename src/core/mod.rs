@@ -1,4 +1,9 @@
 pub mod git;
 pub mod config;
+pub mod check;
+pub mod document;
+pub mod migrate;
+pub mod format;
+pub mod version;
 
 pub use git::GitRepo; 
\ No newline at end of file
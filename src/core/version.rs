@@ -0,0 +1,244 @@
+use std::cmp::Ordering;
+use std::fmt;
+use anyhow::Result;
+
+/// Compares two versions the way pacman's `vercmp` does.
+///
+/// Versions look like `epoch:pkgver-pkgrel`, the release is only compared
+/// when both sides have one, so `1.2` equals `1.2-3`.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let (epoch_a, version_a, release_a) = split(a);
+    let (epoch_b, version_b, release_b) = split(b);
+
+    compare_segments(epoch_a, epoch_b)
+        .then_with(|| compare_segments(version_a, version_b))
+        .then_with(|| match (release_a, release_b) {
+            (Some(a), Some(b)) => compare_segments(a, b),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Splits a version into epoch, version and release
+fn split(version: &str) -> (&str, &str, Option<&str>) {
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) if epoch.chars().all(|c| c.is_ascii_digit()) => (epoch, rest),
+        _ => ("0", version),
+    };
+    match rest.rsplit_once('-') {
+        Some((version, release)) => (epoch, version, Some(release)),
+        None => (epoch, rest, None),
+    }
+}
+
+/// rpmvercmp: compares alternating runs of digits and letters, ignoring separators
+fn compare_segments(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let (mut one, mut two) = (a.as_bytes(), b.as_bytes());
+    while !one.is_empty() && !two.is_empty() {
+        let separators_one = one.iter().take_while(|c| !c.is_ascii_alphanumeric()).count();
+        let separators_two = two.iter().take_while(|c| !c.is_ascii_alphanumeric()).count();
+        one = &one[separators_one..];
+        two = &two[separators_two..];
+
+        if one.is_empty() || two.is_empty() {
+            break;
+        }
+        // Differently long separators end the comparison, like in pacman
+        if separators_one != separators_two {
+            return separators_one.cmp(&separators_two);
+        }
+
+        let is_num = one[0].is_ascii_digit();
+        let same_kind = |c: &u8| if is_num { c.is_ascii_digit() } else { c.is_ascii_alphabetic() };
+        let len_one = one.iter().take_while(|c| same_kind(c)).count();
+        let len_two = two.iter().take_while(|c| same_kind(c)).count();
+        let (segment_one, segment_two) = (&one[..len_one], &two[..len_two]);
+        one = &one[len_one..];
+        two = &two[len_two..];
+
+        // Numbers always beat letters
+        if segment_two.is_empty() {
+            return if is_num { Ordering::Greater } else { Ordering::Less };
+        }
+
+        let ordering = if is_num {
+            let trim = |s: &[u8]| -> Vec<u8> { s.iter().skip_while(|c| **c == b'0').copied().collect() };
+            let (number_one, number_two) = (trim(segment_one), trim(segment_two));
+            number_one.len().cmp(&number_two.len()).then_with(|| number_one.cmp(&number_two))
+        } else {
+            segment_one.cmp(segment_two)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    if one.is_empty() && two.is_empty() {
+        return Ordering::Equal;
+    }
+    // A remaining letter segment never beats an empty one, so `1.0alpha` < `1.0`
+    let alpha = |s: &[u8]| s.first().is_some_and(u8::is_ascii_alphabetic);
+    if (one.is_empty() && !alpha(two)) || alpha(one) {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+/// A `version:` constraint such as `>=0.10` or `>=0.10, <0.11`
+#[derive(Debug, Clone)]
+pub struct VersionReq {
+    clauses: Vec<(Operator, String)>,
+}
+
+impl VersionReq {
+    /// Parses comma separated clauses, a version without an operator must match exactly
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut clauses = Vec::new();
+        for clause in text.split(',').map(str::trim) {
+            let (operator, version) = [
+                (">=", Operator::GreaterOrEqual),
+                ("<=", Operator::LessOrEqual),
+                (">", Operator::Greater),
+                ("<", Operator::Less),
+                ("==", Operator::Equal),
+                ("=", Operator::Equal),
+            ]
+            .iter()
+            .find_map(|(prefix, operator)| clause.strip_prefix(prefix).map(|rest| (*operator, rest.trim())))
+            .unwrap_or((Operator::Equal, clause));
+
+            if version.is_empty() {
+                anyhow::bail!("`{}` is missing a version", text);
+            }
+            if version.contains(char::is_whitespace) || version.starts_with(['<', '>', '=']) {
+                anyhow::bail!("`{}` is not a valid version constraint", text);
+            }
+            clauses.push((operator, version.to_string()));
+        }
+        Ok(Self { clauses })
+    }
+
+    pub fn matches(&self, installed: &str) -> bool {
+        self.clauses.iter().all(|(operator, version)| {
+            let ordering = compare(installed, version);
+            match operator {
+                Operator::Equal => ordering == Ordering::Equal,
+                Operator::Greater => ordering == Ordering::Greater,
+                Operator::GreaterOrEqual => ordering != Ordering::Less,
+                Operator::Less => ordering == Ordering::Less,
+                Operator::LessOrEqual => ordering != Ordering::Greater,
+            }
+        })
+    }
+
+    /// The version to install when the constraint pins a single version
+    pub fn exact(&self) -> Option<&str> {
+        match self.clauses.as_slice() {
+            [(Operator::Equal, version)] => Some(version),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let clauses: Vec<String> = self.clauses
+            .iter()
+            .map(|(operator, version)| {
+                let operator = match operator {
+                    Operator::Equal => "=",
+                    Operator::Greater => ">",
+                    Operator::GreaterOrEqual => ">=",
+                    Operator::Less => "<",
+                    Operator::LessOrEqual => "<=",
+                };
+                format!("{}{}", operator, version)
+            })
+            .collect();
+        write!(f, "{}", clauses.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_like_vercmp() {
+        assert_eq!(compare("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(compare("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare("1.0.1", "1.0"), Ordering::Greater);
+        assert_eq!(compare("1.01", "1.1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn epoch_wins_over_version() {
+        assert_eq!(compare("1:1.0", "2.0"), Ordering::Greater);
+        assert_eq!(compare("1:1.0", "2:0.1"), Ordering::Less);
+        assert_eq!(compare("0:1.0", "1.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn release_only_counts_on_both_sides() {
+        assert_eq!(compare("1.0-2", "1.0-10"), Ordering::Less);
+        assert_eq!(compare("1.0-2", "1.0"), Ordering::Equal);
+        assert_eq!(compare("1.0", "1.0-2"), Ordering::Equal);
+    }
+
+    #[test]
+    fn letters_sort_before_numbers() {
+        assert_eq!(compare("1.0alpha", "1.0"), Ordering::Less);
+        assert_eq!(compare("1.0alpha", "1.0beta"), Ordering::Less);
+        assert_eq!(compare("1.0a", "1.0.1"), Ordering::Less);
+        assert_eq!(compare("1.0rc1", "1.0"), Ordering::Less);
+        assert_eq!(compare("1.a", "1.1"), Ordering::Less);
+    }
+
+    #[test]
+    fn parses_constraints() {
+        let req = VersionReq::parse(">=0.10, <0.11").unwrap();
+        assert_eq!(req.to_string(), ">=0.10, <0.11");
+        assert!(req.matches("0.10.2-1"));
+        assert!(!req.matches("0.11.0-1"));
+        assert!(!req.matches("0.9.5-1"));
+        assert_eq!(req.exact(), None);
+
+        assert!(VersionReq::parse("").is_err());
+        assert!(VersionReq::parse(">=").is_err());
+        assert!(VersionReq::parse(">= 1.0 2.0").is_err());
+        assert!(VersionReq::parse(">>1.0").is_err());
+    }
+
+    #[test]
+    fn plain_version_is_exact() {
+        let req = VersionReq::parse("3.12.7-1").unwrap();
+        assert_eq!(req.exact(), Some("3.12.7-1"));
+        assert!(req.matches("3.12.7-1"));
+        assert!(!req.matches("3.12.7-2"));
+
+        assert_eq!(VersionReq::parse("=1.0").unwrap().exact(), Some("1.0"));
+        assert_eq!(VersionReq::parse("==1.0").unwrap().exact(), Some("1.0"));
+        assert_eq!(VersionReq::parse("<=1.0").unwrap().exact(), None);
+    }
+
+    #[test]
+    fn operators() {
+        assert!(VersionReq::parse(">1.0").unwrap().matches("1.1"));
+        assert!(!VersionReq::parse(">1.0").unwrap().matches("1.0"));
+        assert!(VersionReq::parse("<=1.0").unwrap().matches("1.0"));
+        assert!(VersionReq::parse("<1:0.1").unwrap().matches("9.9"));
+    }
+}
@@ -1,17 +1,30 @@
 use clap::Parser;
 use dotty::{
-    cli::Args,
-    core::{GitRepo, config::DottyConfig},
+    cli::{Args, Command},
+    core::{GitRepo, config::{DottyConfig, Distro, FileMapping, Package, Service, Source, VersionMismatch}, check::{check_repo, Severity}, migrate,
+        format::{self, find_config, ConfigFormat}},
     utils::{
-        output::{print_step, print_success, print_error, print_status},
+        output::{print_section, print_step, print_success, print_error, print_status, print_warning, print_diff,
+            print_subsection, print_list_item},
+        status::{mapping_status, State},
+        select::{select, Item, Selection},
+        hooks::{hooks_for, run_hooks, Hook, Stage},
+        services,
+        render::SourceReader,
+        secrets::referenced_secrets,
+        system_settings::{self, Machine, Setting, SettingState},
+        process::{self, print_failure_details},
+        prompt::{self, confirm, input_mode, InputMode},
         spinner::with_spinner,
-        system::{detect_aur_helper, AURHelper, prompt_aur_helper_installation},
-        install::{pacman, aur},
-        files::copy_files,
+        system::{detect_aur_helper, detect_distro, ensure_aur_helper, AURHelper, prompt_aur_helper_installation},
+        install::{archive::PackageArchive, aur::AurBackend, backend::{backend_for, PackageBackend, SystemRunner}, InstalledPackages, PackageState,
+            resolve::{resolve_packages, Resolution, Target}, verify::{verify_packages, SystemPackageSource}},
+        files::{copy_files, remove_deployed, Removal},
+        state::{DeployedFile, InstalledPackage, MachineState},
         backup::BackupManager,
     },
 };
-use std::{path::PathBuf, io::{self, Write}};
+use std::path::{Path, PathBuf};
 
 fn get_cache_dir() -> PathBuf {
     dirs::cache_dir()
@@ -26,99 +39,1339 @@ fn ensure_cache_dir() -> std::io::Result<PathBuf> {
     Ok(cache_dir)
 }
 
-fn display_packages_and_confirm(config: &DottyConfig) -> bool {
-    let (aur_packages, normal_packages): (Vec<_>, Vec<_>) = config.packages
-        .iter()
-        .partition(|p| p.is_aur);
+fn print_package_list(title: &str, packages: &[String]) {
+    if packages.is_empty() {
+        return;
+    }
+    print_status(title);
+    for package in packages {
+        print_status(format!("    • {}", package));
+    }
+}
+
+/// Whether installing a `source` package in `state` changes it.
+///
+/// Upgrading single packages with `pacman -S` is a partial upgrade, which Arch
+/// does not support, so outdated official packages are only reported and left
+/// to `pacman -Syu`.
+fn needs_install(source: Source, state: PackageState) -> bool {
+    match state {
+        PackageState::Missing => true,
+        PackageState::Outdated => source != Source::Pacman,
+        PackageState::Installed => false,
+    }
+}
+
+const PACMAN_UPGRADES: &str = "Upgrades available from the official repositories (run `sudo pacman -Syu` to install them):";
+
+/// Sources in the order their packages are listed and installed
+const SOURCE_ORDER: &[Source] = &[
+    Source::Pacman, Source::Apt, Source::Dnf, Source::Aur,
+    Source::Flatpak, Source::Cargo, Source::Pipx, Source::Npm,
+];
+
+fn packages_in_state(packages: &[&Target], db: &InstalledPackages, state: PackageState) -> Vec<String> {
+    packages.iter()
+        .filter(|p| db.state(p.source, &p.name) == state)
+        .map(|p| p.label())
+        .collect()
+}
+
+/// Reads the installed packages of every source the configuration uses
+fn query_installed(config: &DottyConfig, distro: Option<Distro>, helper: AURHelper) -> InstalledPackages {
+    let mut installed = InstalledPackages::default();
+
+    let used = |source: Source| {
+        source.is_supported_on(distro) && config.packages.iter().any(|p| p.source(distro) == Some(source))
+    };
+    for source in SOURCE_ORDER.iter().copied().filter(|s| used(*s)) {
+        let backend = backend_for(source, helper, &SystemRunner);
+        // Missing package managers are reported when listing the packages
+        if source != Source::Aur && !backend.is_available() {
+            continue;
+        }
+        match backend.query() {
+            Ok(db) => installed.insert(source, db),
+            Err(e) => print_warning(format!(
+                "Could not read installed {} packages, assuming none are installed: {}", source, e)),
+        }
+    }
+
+    installed
+}
+
+/// Backend for `source`, AUR packages are reviewed before building if `review` is set
+fn backend(source: Source, helper: AURHelper, review: bool) -> Box<dyn PackageBackend> {
+    match source {
+        Source::Aur => Box::new(AurBackend::new(helper, &SystemRunner).with_review(review)),
+        source => backend_for(source, helper, &SystemRunner),
+    }
+}
+
+fn display_packages_and_confirm(
+    resolution: &Resolution,
+    db: &InstalledPackages,
+    helper: &mut AURHelper,
+    review: bool,
+) -> bool {
+    let targets = &resolution.targets;
+    let pending = |source: Source| -> Vec<&Target> {
+        targets.iter()
+            .filter(|t| t.source == source && t.pinned.is_none())
+            .filter(|t| needs_install(source, db.state(source, &t.name)))
+            .collect()
+    };
+    let pinned: Vec<_> = targets.iter()
+        .filter(|t| t.needs_pinned_install(db))
+        .filter_map(|t| t.pinned.as_ref().map(|version| match db.version(t.source, &t.name) {
+            Some(installed) => format!("{} {} (installed {})", t.name, version, installed),
+            None => format!("{} {}", t.name, version),
+        }))
+        .collect();
+
+    // Pinned packages at their version count as installed, even when outdated
+    let installed: Vec<_> = targets.iter()
+        .filter(|p| match p.pinned {
+            Some(_) => !p.needs_pinned_install(db),
+            None => db.state(p.source, &p.name) == PackageState::Installed,
+        })
+        .map(|p| match p.source {
+            Source::Pacman | Source::Apt | Source::Dnf => p.label(),
+            source => format!("{} [{}]", p.label(), source),
+        })
+        .collect();
+    let unsupported: Vec<_> = resolution.unsupported.iter()
+        .map(|(name, reason)| format!("{} ({})", name, reason))
+        .collect();
 
     println!();
+    print_package_list("Already installed:", &installed);
+    print_package_list("Skipped:", &unsupported);
+    let official: Vec<&Target> = targets.iter()
+        .filter(|t| t.source == Source::Pacman && t.pinned.is_none())
+        .collect();
+    print_package_list(PACMAN_UPGRADES, &packages_in_state(&official, db, PackageState::Outdated));
 
-    if !normal_packages.is_empty() {
-        print_status("Packages to be installed from official repositories:");
-        let packages: Vec<_> = normal_packages.iter().map(|p| &p.name).collect();
-        for package in packages {
-            print_status(&format!("    • {}", package));
+    if pinned.is_empty() && SOURCE_ORDER.iter().all(|source| pending(*source).is_empty()) {
+        println!();
+        if unsupported.is_empty() {
+            print_success("All packages are already installed");
+        } else {
+            print_success("All supported packages are already installed");
         }
+        return true;
     }
 
-    if !aur_packages.is_empty() {
-        match detect_aur_helper() {
-            AURHelper::None => {
+    let mut missing_tools = false;
+    for source in SOURCE_ORDER.iter().copied() {
+        let packages = pending(source);
+        if packages.is_empty() {
+            continue;
+        }
+
+        if source == Source::Aur {
+            let aur_to_install: Vec<_> = packages.iter().map(|p| p.label()).collect();
+            // Reviewed packages are always built by dotty, no helper is needed
+            if *helper == AURHelper::None && !review {
+                if input_mode() != InputMode::Interactive {
+                    println!();
+                    print_error("No AUR helper found");
+                    print_package_list("The following packages need to be installed from AUR:", &aur_to_install);
+                    println!();
+                    print_status("Pass --aur-helper paru, yay or builtin, or --aur-helper none to skip them.");
+                    std::process::exit(1);
+                }
                 match prompt_aur_helper_installation() {
-                    Some(helper) => {
-                        println!();
-                        print_status(&format!("Packages to be installed from AUR (using {}):", 
-                            helper.command().unwrap()));
-                        let packages: Vec<_> = aur_packages.iter().map(|p| &p.name).collect();
-                        for package in packages {
-                            print_status(&format!("    • {}", package));
-                        }
-                    }
+                    Some(chosen) => *helper = chosen,
                     None => {
                         println!();
                         print_error("AUR helper required");
-                        print_status("The following packages need to be installed from AUR:");
-                        let packages: Vec<_> = aur_packages.iter().map(|p| &p.name).collect();
-                        for package in packages {
-                            print_status(&format!("    • {}", package));
-                        }
+                        print_package_list("The following packages need to be installed from AUR:", &aur_to_install);
                         println!();
                         print_status("Please install either paru or yay and try again.");
                         std::process::exit(1);
                     }
                 }
+            } else if !review {
+                // A helper picked with --aur-helper may still have to be installed
+                match ensure_aur_helper(*helper) {
+                    Some(ready) => *helper = ready,
+                    None => std::process::exit(1),
+                }
             }
-            helper => {
-                println!();
-                print_status(&format!("Packages to be installed from AUR (using {}):", 
-                    helper.command().unwrap()));
-                let packages: Vec<_> = aur_packages.iter().map(|p| &p.name).collect();
-                for package in packages {
-                    print_status(&format!("    • {}", package));
+            let backend = backend(source, *helper, review);
+            if !backend.is_available() {
+                print_error(format!("{} is not available, it is needed for:", backend.name()));
+                for package in &aur_to_install {
+                    print_status(format!("    • {}", package));
                 }
+                missing_tools = true;
+                continue;
             }
+            println!();
+            print_package_list(&format!("Packages to be installed from {}:", backend.name()), &aur_to_install);
+            continue;
         }
-    }
 
-    println!();
-    print!("Do you want to proceed with the installation? [Y/n] ");
-    io::stdout().flush().unwrap();
+        let backend = backend_for(source, AURHelper::None, &SystemRunner);
+        if !backend.is_available() {
+            print_error(format!("{} is not installed, it is needed for:", source));
+            for package in &packages {
+                print_status(format!("    • {}", package.label()));
+            }
+            missing_tools = true;
+            continue;
+        }
+
+        match source {
+            Source::Pacman | Source::Apt | Source::Dnf => {
+                print_package_list(&format!("Packages to be installed from {}:", backend.name()),
+                    &packages_in_state(&packages, db, PackageState::Missing));
+                print_package_list(&format!("Packages to be upgraded from {}:", backend.name()),
+                    &packages_in_state(&packages, db, PackageState::Outdated));
+            }
+            _ => {
+                let labels: Vec<_> = packages.iter().map(|p| p.label()).collect();
+                print_package_list(&format!("Packages to be installed with {}:", backend.name()), &labels);
+            }
+        }
+    }
+    print_package_list("Packages to be installed at a pinned version:", &pinned);
+    if missing_tools {
+        println!();
+        print_status("Install the missing package managers and try again.");
+        std::process::exit(1);
+    }
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-    
-    let input = input.trim().to_lowercase();
     println!();
-    !matches!(input.as_str(), "n" | "no")
+    confirm("Do you want to proceed with the installation?")
 }
 
-fn install_packages(config: &DottyConfig) -> Result<(), anyhow::Error> {
-    let (aur_packages, normal_packages): (Vec<_>, Vec<_>) = config.packages
-        .iter()
-        .partition(|p| p.is_aur);
+fn install_packages(
+    targets: &[Target],
+    db: &InstalledPackages,
+    helper: AURHelper,
+    review: bool,
+    archive: &PackageArchive,
+) -> Result<(), anyhow::Error> {
+    let mut state = MachineState::load()?;
+    let mut failed = Vec::new();
+    let mut attempted = 0;
+    // Only packages dotty brought onto the system are recorded, so prune never
+    // removes one that was installed before and merely upgraded or pinned
+    let record = |state: &mut MachineState, source: Source, name: &str| {
+        let target = targets.iter().find(|t| t.source == source && t.name == name);
+        if let Some(target) = target.filter(|_| db.state(source, name) == PackageState::Missing) {
+            state.record_package(InstalledPackage {
+                name: name.to_string(),
+                source,
+                package: target.package.clone(),
+            });
+        }
+    };
 
-    if !normal_packages.is_empty() {
-        print_status("Installing packages from official repositories...");
-        let packages: Vec<_> = normal_packages.iter().map(|p| p.name.as_str()).collect();
-        pacman::install_packages(&packages)?;
+    let pinned: Vec<(&str, &str)> = targets.iter()
+        .filter(|t| t.needs_pinned_install(db))
+        .filter_map(|t| t.pinned.as_deref().map(|version| (t.name.as_str(), version)))
+        .collect();
+    if !pinned.is_empty() {
+        println!();
+        print_status("Installing pinned versions...");
+        let report = archive.install(&SystemRunner, &pinned)?;
+        attempted += pinned.len();
+        for name in &report.installed {
+            record(&mut state, Source::Pacman, name);
+        }
+        failed.extend(report.failed);
     }
 
-    if !aur_packages.is_empty() {
-        let helper = detect_aur_helper();
-        if let AURHelper::None = helper {
-            anyhow::bail!("No AUR helper found for installing AUR packages");
+    for source in SOURCE_ORDER.iter().copied() {
+        let packages: Vec<&str> = targets.iter()
+            .filter(|t| t.source == source && t.pinned.is_none())
+            .filter(|t| needs_install(source, db.state(source, &t.name)))
+            .map(|t| t.name.as_str())
+            .collect();
+        if packages.is_empty() {
+            continue;
         }
 
+        let backend = backend(source, helper, review);
+        if !backend.is_available() {
+            anyhow::bail!("{} is not available for installing {}", backend.name(), packages.join(", "));
+        }
+
+        println!();
+        print_status(format!("Installing packages from {}...", backend.name()));
+        let report = backend.install(&packages)?;
+        attempted += packages.len();
+        for name in &report.installed {
+            record(&mut state, source, name);
+        }
+        failed.extend(report.failed);
+    }
+
+    if let Err(e) = state.save() {
+        print_warning(format!("Could not record installed packages: {}", e));
+    }
+
+    if attempted == 0 {
+        return Ok(());
+    }
+
+    if !failed.is_empty() {
         println!();
-        print_status("Installing packages from AUR...");
-        let packages: Vec<_> = aur_packages.iter().map(|p| p.name.as_str()).collect();
-        aur::install_aur_packages(helper, &packages)?;
+        print_error("The following packages could not be installed:");
+        for package in &failed {
+            print_status(format!("    • {}", package));
+        }
+        anyhow::bail!("{} of {} packages failed to install", failed.len(), attempted);
     }
 
     println!();
     print_success("All packages installed successfully!");
     Ok(())
 }
+
+/// Compares installed versions with the `version` constraints of the configuration
+fn check_versions(config: &DottyConfig, targets: &[Target], distro: Option<Distro>, helper: AURHelper) {
+    let constrained: Vec<_> = config.packages.iter()
+        .filter_map(|p| Some((p, p.version_req().ok().flatten()?)))
+        .collect();
+    if constrained.is_empty() {
+        return;
+    }
+
+    // Installation changed the versions, read them again
+    let db = query_installed(config, distro, helper);
+    let mut mismatched = 0;
+    for (package, req) in constrained {
+        for target in targets.iter().filter(|t| t.package == package.name) {
+            match db.version(target.source, &target.name) {
+                Some(version) if req.matches(version) => {}
+                Some(version) => {
+                    mismatched += 1;
+                    print_warning(format!("{} {} does not satisfy {}", target.name, version, req));
+                }
+                // Packages that failed to install were already reported
+                None if db.state(target.source, &target.name) == PackageState::Missing => {}
+                None => print_warning(format!(
+                    "Cannot check the version of {}, {} does not report versions", target.name, target.source)),
+            }
+        }
+    }
+
+    if mismatched > 0 && config.version_mismatch() == VersionMismatch::Fail {
+        print_error(format!("{} package(s) do not match their version constraint", mismatched));
+        std::process::exit(1);
+    }
+}
+
+/// Makes sure every package that still needs installing exists where the configuration says
+fn verify_packages_or_exit(targets: &[Target], db: &InstalledPackages) {
+    let pending: Vec<&Target> = targets.iter()
+        .filter(|p| db.state(p.source, &p.name) == PackageState::Missing)
+        .collect();
+
+    let verification = with_spinner("Verifying package names...", || {
+        verify_packages(&pending, &SystemPackageSource)
+    });
+
+    for reason in &verification.skipped {
+        print_warning(reason);
+    }
+    for issue in &verification.issues {
+        if issue.is_error() {
+            print_error(issue.message());
+        } else {
+            print_warning(issue.message());
+        }
+    }
+
+    if verification.has_errors() {
+        print_error("Fix the package names in the configuration and try again");
+        std::process::exit(1);
+    }
+}
+
+/// Remembers deployed files so `dotty prune` can remove them later
+fn record_files(deployed: Vec<DeployedFile>) {
+    let result = MachineState::load().and_then(|mut state| {
+        for file in deployed {
+            state.record_file(file);
+        }
+        state.save()
+    });
+    if let Err(e) = result {
+        print_warning(format!("Could not record deployed files: {}", e));
+    }
+}
+
+/// Short state of a configured package for the selector
+fn package_status(package: &str, resolution: &Resolution, db: &InstalledPackages) -> &'static str {
+    let targets: Vec<_> = resolution.targets.iter().filter(|t| t.package == package).collect();
+    if targets.is_empty() {
+        return "skipped";
+    }
+
+    let states: Vec<_> = targets.iter()
+        .map(|t| match t.pinned {
+            Some(_) if t.needs_pinned_install(db) => PackageState::Outdated,
+            Some(_) => PackageState::Installed,
+            None => db.state(t.source, &t.name),
+        })
+        .collect();
+    if states.contains(&PackageState::Missing) {
+        "new"
+    } else if states.contains(&PackageState::Outdated) {
+        "outdated"
+    } else {
+        "installed"
+    }
+}
+
+/// Short state of a file mapping for the selector
+fn file_status(repo_path: &Path, mapping: &FileMapping, reader: &SourceReader) -> &'static str {
+    match mapping_status(repo_path, mapping, reader).map(|status| status.state) {
+        Ok(State::InSync) => "installed",
+        Ok(State::Missing) => "new",
+        Ok(State::Modified | State::Drifted) => "drifted",
+        Err(_) => "unreadable",
+    }
+}
+
+/// Lets the user pick from the packages and file mappings in `selection`,
+/// exits if the selection is cancelled.
+///
+/// Package statuses come from `packages`, which is only needed when packages are selected.
+fn select_or_exit(
+    repo_path: &Path,
+    config: &DottyConfig,
+    selection: &Selection,
+    packages: Option<(&Resolution, &InstalledPackages)>,
+) -> Selection {
+    if input_mode() != InputMode::Interactive {
+        print_error("--select needs a terminal to pick from");
+        print_status("Run dotty in a terminal, or leave out --select to install everything");
+        std::process::exit(1);
+    }
+
+    let reader = SourceReader::new(config);
+    // Each line of the checklist is a package or one of its mappings,
+    // anything already filtered out on the command line is not offered
+    let mut entries = Vec::new();
+    let mut items = Vec::new();
+    for (i, package) in config.packages.iter().enumerate() {
+        if let Some((resolution, db)) = packages.filter(|_| selection.package(i)) {
+            entries.push((i, None));
+            items.push(Item {
+                label: package.name.clone(),
+                status: package_status(&package.name, resolution, db).to_string(),
+                nested: false,
+                selected: true,
+            });
+        }
+        for (j, mapping) in package.files.iter().enumerate().filter(|(j, _)| selection.file(i, *j)) {
+            entries.push((i, Some(j)));
+            items.push(Item {
+                label: format!("{} → {}", mapping.source, mapping.destination),
+                status: file_status(repo_path, mapping, &reader).to_string(),
+                nested: selection.package(i),
+                selected: true,
+            });
+        }
+    }
+    if items.is_empty() {
+        return selection.clone();
+    }
+
+    let chosen = match select("Select the packages and files to install", &items) {
+        Ok(Some(chosen)) => chosen,
+        Ok(None) => {
+            print_status("Installation cancelled.");
+            std::process::exit(0);
+        }
+        Err(e) => {
+            print_error(format!("Failed to show the selection: {}", e));
+            std::process::exit(1);
+        }
+    };
+
+    let mut selection = selection.clone();
+    for ((package, mapping), selected) in entries.into_iter().zip(chosen) {
+        match mapping {
+            Some(mapping) => selection.set_file(package, mapping, selected),
+            None => selection.set_package(package, selected),
+        }
+    }
+    selection
+}
+
+/// Packages and files to install according to `--only`, `--except`,
+/// `--files-only` and `--packages-only`
+fn selection_from_args(config: &DottyConfig, args: &Args) -> Selection {
+    let mut selection = Selection::all(config);
+    if let Err(e) = selection.filter(config, &args.only, &args.except) {
+        print_error(e);
+        std::process::exit(1);
+    }
+    if args.files_only {
+        selection.without_packages();
+    }
+    if args.packages_only {
+        selection.without_files();
+    }
+    selection
+}
+
+fn print_hook_list(title: &str, hooks: &[Hook]) {
+    if hooks.is_empty() {
+        return;
+    }
+    println!();
+    let labels: Vec<_> = hooks.iter().map(Hook::label).collect();
+    print_package_list(title, &labels);
+}
+
+fn service_label(service: &Service) -> String {
+    let actions: Vec<_> = services::wanted(service).iter().map(ToString::to_string).collect();
+    if actions.is_empty() {
+        return format!("{} ({})", service.unit, service.scope);
+    }
+    format!("{} ({}: {})", service.unit, service.scope, actions.join(", "))
+}
+
+fn print_service_list(packages: &[&Package]) {
+    let labels: Vec<_> = packages.iter().flat_map(|p| &p.services).map(service_label).collect();
+    if labels.is_empty() {
+        return;
+    }
+    println!();
+    print_package_list("Services to set up:", &labels);
+}
+
+/// Enables, starts or masks the services of `packages`, returns how many failed
+fn apply_services(packages: &[&Package]) -> usize {
+    let all: Vec<&Service> = packages.iter().flat_map(|p| &p.services).collect();
+    if all.is_empty() {
+        return 0;
+    }
+
+    print_section("Setting up services");
+    let mut failed = 0;
+    for service in all {
+        let label = format!("{} ({})", service.unit, service.scope);
+        match services::apply(&SystemRunner, service) {
+            Ok(actions) if actions.is_empty() => print_success(format!("{} is already set up", label)),
+            Ok(actions) => {
+                let actions: Vec<_> = actions.iter().map(ToString::to_string).collect();
+                print_success(format!("{}: {}", label, actions.join(", ")));
+            }
+            Err(e) => {
+                failed += 1;
+                print_error(format!("{}: {}", label, e));
+                print_failure_details(&e);
+            }
+        }
+    }
+    failed
+}
+
+fn setting_label(state: &SettingState) -> String {
+    match &state.current {
+        Some(current) if !state.is_applied() => format!("{} (currently {})", state.setting, current),
+        _ => state.setting.to_string(),
+    }
+}
+
+/// Compares the `system:` section with this machine
+fn inspect_system_settings(config: &DottyConfig) -> anyhow::Result<Vec<SettingState>> {
+    let machine = Machine::new(&SystemRunner)?;
+    system_settings::settings(&config.system).iter().map(|setting| machine.inspect(setting)).collect()
+}
+
+/// Applies the settings of the `system:` section that differ, returns how many failed
+fn apply_system_settings(config: &DottyConfig) -> usize {
+    let pending: Vec<SettingState> = match inspect_system_settings(config) {
+        Ok(states) => states.into_iter().filter(|s| !s.is_applied()).collect(),
+        Err(e) => {
+            print_error(format!("Failed to read the system settings: {}", e));
+            return 1;
+        }
+    };
+    if pending.is_empty() {
+        println!();
+        print_success("System settings are already applied");
+        return 0;
+    }
+
+    println!();
+    let labels: Vec<_> = pending.iter().map(setting_label).collect();
+    print_package_list("System settings to change:", &labels);
+    println!();
+    if !confirm("Do you want to apply these system settings?") {
+        print_status("System settings skipped.");
+        return 0;
+    }
+
+    print_section("Applying system settings");
+    let machine = match Machine::new(&SystemRunner) {
+        Ok(machine) => machine,
+        Err(e) => {
+            print_error(e);
+            return 1;
+        }
+    };
+    let mut failed = 0;
+    for state in &pending {
+        if let Some(problem) = &state.problem {
+            failed += 1;
+            print_error(format!("Cannot set {}: {}", state.setting, problem));
+            continue;
+        }
+        match machine.apply(&state.setting) {
+            Ok(()) => print_success(format!("Set {}", state.setting)),
+            Err(e) => {
+                failed += 1;
+                print_error(e.to_string());
+                print_failure_details(&e);
+            }
+        }
+    }
+    if pending.iter().any(|s| matches!(s.setting, Setting::Group(_) | Setting::Shell(_))) {
+        print_status("Log out and back in for the new shell and groups to take effect");
+    }
+    failed
+}
+
+/// Prints everything an installation would do without changing anything
+fn print_dry_run(
+    repo_path: &Path,
+    config: &DottyConfig,
+    selection: &Selection,
+    packages: Option<(&Resolution, &InstalledPackages)>,
+    system: bool,
+) {
+    print_step("Dry run, nothing will be changed");
+    let selected_packages = selection.packages(config);
+    let selected: Vec<&str> = selected_packages.iter().map(|p| p.name.as_str()).collect();
+
+    if let Some((resolution, db)) = packages.filter(|_| !selected.is_empty()) {
+        let targets: Vec<&Target> = resolution.targets.iter()
+            .filter(|t| selected.contains(&t.package.as_str()))
+            .collect();
+        let (pinned, unpinned): (Vec<&Target>, Vec<&Target>) = targets.iter().partition(|t| t.pinned.is_some());
+
+        println!();
+        for source in SOURCE_ORDER.iter().copied() {
+            let from_source: Vec<&Target> = unpinned.iter().copied().filter(|t| t.source == source).collect();
+            print_package_list(&format!("Packages to be installed from {}:", source),
+                &packages_in_state(&from_source, db, PackageState::Missing));
+            let upgrades = packages_in_state(&from_source, db, PackageState::Outdated);
+            match source {
+                Source::Pacman => print_package_list(PACMAN_UPGRADES, &upgrades),
+                _ => print_package_list(&format!("Packages to be upgraded from {}:", source), &upgrades),
+            }
+        }
+        let pinned: Vec<_> = pinned.iter()
+            .filter(|t| t.needs_pinned_install(db))
+            .map(|t| format!("{} {}", t.name, t.pinned.as_deref().unwrap_or_default()))
+            .collect();
+        print_package_list("Packages to be installed at a pinned version:", &pinned);
+        let skipped: Vec<_> = resolution.unsupported.iter()
+            .filter(|(name, _)| selected.contains(&name.as_str()))
+            .map(|(name, reason)| format!("{} ({})", name, reason))
+            .collect();
+        print_package_list("Skipped:", &skipped);
+        let installed = targets.iter().filter(|t| package_status(&t.package, resolution, db) == "installed").count();
+        print_status(format!("{} package(s) are already installed", installed));
+
+        print_hook_list("Hooks to run before installing:", &hooks_for(Stage::PreInstall, &config.hooks, &selected_packages));
+        print_hook_list("Hooks to run after installing:", &hooks_for(Stage::PostInstall, &config.hooks, &selected_packages));
+
+        let services: Vec<_> = selected_packages.iter()
+            .flat_map(|p| &p.services)
+            .filter(|service| services::query(&SystemRunner, service).map_or(true, |state| !state.pending(service).is_empty()))
+            .map(service_label)
+            .collect();
+        if !services.is_empty() {
+            println!();
+            print_package_list("Services to set up:", &services);
+        }
+    }
+
+    let files = selection.files(config);
+    if !files.is_empty() {
+        println!();
+        let reader = SourceReader::new(config);
+        let labels: Vec<_> = files.iter()
+            .map(|(_, mapping)| format!("{} → {} ({}){}", mapping.source, mapping.destination,
+                file_status(repo_path, mapping, &reader), template_note(repo_path, mapping)))
+            .collect();
+        print_package_list("Files to be copied:", &labels);
+
+        let mut with_files: Vec<&Package> = files.iter().map(|(package, _)| *package).collect();
+        with_files.dedup_by(|a, b| a.name == b.name);
+        print_hook_list("Hooks to run after copying:", &hooks_for(Stage::PostFiles, &config.hooks, &with_files));
+    }
+
+    if system {
+        println!();
+        match inspect_system_settings(config) {
+            Ok(states) => {
+                let labels: Vec<_> = states.iter().filter(|s| !s.is_applied()).map(setting_label).collect();
+                if labels.is_empty() {
+                    print_status("System settings are already applied");
+                }
+                print_package_list("System settings to change:", &labels);
+            }
+            Err(e) => print_warning(format!("Could not read the system settings: {}", e)),
+        }
+    }
+}
+
+/// Checks the configuration in `repo_path` and runs the full installation
+fn install_from(repo_path: &Path, args: &Args) {
+    print_status("Checking for dotty configuration...");
+    let report = match check_repo(repo_path) {
+        Ok(report) => report,
+        Err(e) => {
+            print_error(format!("Failed to read configuration: {}", e));
+            print_status("Make sure the folder contains a valid dotty.yaml, dotty.toml or dotty.json file");
+            std::process::exit(1);
+        }
+    };
+
+    report.print();
+    if report.has_errors() {
+        print_error(format!("Invalid configuration in {}", report.file.display()));
+        print_status("Run dotty check for a full report");
+        std::process::exit(1);
+    }
+
+    let config = match DottyConfig::from_file(&report.file) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(format!("Failed to read configuration: {}", e));
+            std::process::exit(1);
+        }
+    };
+    print_success(format!("Found valid configuration in {}", report.file.display()));
+
+    let mut selection = selection_from_args(&config, args);
+
+    // Resolve the packages if any are selected
+    let mut packages = None;
+    if !selection.packages(&config).is_empty() {
+        let distro = detect_distro();
+        let helper = args.aur_helper.unwrap_or_else(detect_aur_helper);
+        let db = query_installed(&config, distro, helper);
+
+        let mut resolution = resolve_packages(&config.packages, distro, &db, &SystemPackageSource);
+        if args.aur_helper == Some(AURHelper::None) {
+            let (aur, targets): (Vec<Target>, Vec<Target>) =
+                resolution.targets.into_iter().partition(|t| t.source == Source::Aur);
+            resolution.targets = targets;
+            resolution.unsupported.extend(aur.into_iter()
+                .map(|t| (t.name, "AUR disabled with --aur-helper none".to_string())));
+        }
+        packages = Some((distro, helper, db, resolution));
+    }
+
+    if args.select {
+        let statuses = packages.as_ref().map(|(_, _, db, resolution)| (resolution, db));
+        selection = select_or_exit(repo_path, &config, &selection, statuses);
+    }
+
+    // System settings are not tied to a package, filtering by package leaves them out
+    let system = !config.system.is_empty() && !args.files_only && args.only.is_empty();
+    if selection.packages(&config).is_empty() && selection.files(&config).is_empty() && !system {
+        print_warning("Nothing is selected, there is nothing to install");
+        return;
+    }
+
+    if args.dry_run {
+        let statuses = packages.as_ref().map(|(_, _, db, resolution)| (resolution, db));
+        print_dry_run(repo_path, &config, &selection, statuses, system);
+        return;
+    }
+
+    // Install the selected packages
+    let mut failed_hooks = 0;
+    let mut installed_packages = false;
+    let selected_packages = selection.packages(&config);
+    let selected: Vec<&str> = selected_packages.iter().map(|p| p.name.as_str()).collect();
+    if let Some((distro, mut helper, db, mut resolution)) = packages.filter(|_| !selected.is_empty()) {
+        let review = config.should_review_pkgbuilds();
+        resolution.targets.retain(|t| selected.contains(&t.package.as_str()));
+        resolution.unsupported.retain(|(name, _)| selected.contains(&name.as_str()));
+        verify_packages_or_exit(&resolution.targets, &db);
+
+        let pre_install = hooks_for(Stage::PreInstall, &config.hooks, &selected_packages);
+        let post_install = hooks_for(Stage::PostInstall, &config.hooks, &selected_packages);
+        print_hook_list("Hooks to run before installing:", &pre_install);
+        print_hook_list("Hooks to run after installing:", &post_install);
+        print_service_list(&selected_packages);
+
+        if !display_packages_and_confirm(&resolution, &db, &mut helper, review) {
+            print_status("Installation cancelled.");
+            std::process::exit(0);
+        }
+
+        failed_hooks += run_hooks(Stage::PreInstall, repo_path, &pre_install);
+        let archive = PackageArchive::new(config.package_cache(), config.archive_mirror());
+        if let Err(e) = install_packages(&resolution.targets, &db, helper, review, &archive) {
+            print_error(format!("Failed to install packages: {}", e));
+            print_failure_details(&e);
+            std::process::exit(1);
+        }
+
+        check_versions(&config, &resolution.targets, distro, helper);
+        failed_hooks += run_hooks(Stage::PostInstall, repo_path, &post_install);
+        installed_packages = true;
+    }
+
+    // Copy the selected dotfiles
+    let all_files = selection.files(&config);
+    if !all_files.is_empty() {
+        println!();
+        print_status("Files to be copied:");
+        for (_, mapping) in &all_files {
+            let source = repo_path.join(&mapping.source);
+
+            if mapping.encrypted {
+                print_status(format!("    • Encrypted file: {} → {}{}",
+                    mapping.source, mapping.destination, template_note(repo_path, mapping)));
+            } else if mapping.template {
+                print_status(format!("    • Template: {} → {}{}",
+                    mapping.source, mapping.destination, template_note(repo_path, mapping)));
+            } else if source.is_dir() {
+                print_status(format!("    • Directory: {} → {}", 
+                    mapping.source, mapping.destination));
+            } else {
+                print_status(format!("    • File: {} → {}", 
+                    mapping.source, mapping.destination));
+            }
+        }
+
+        // Only packages with files being copied run their post_files hooks
+        let mut with_files: Vec<&Package> = all_files.iter().map(|(package, _)| *package).collect();
+        with_files.dedup_by(|a, b| a.name == b.name);
+        let post_files = hooks_for(Stage::PostFiles, &config.hooks, &with_files);
+        print_hook_list("Hooks to run after copying:", &post_files);
+
+        println!();
+        if !confirm("Do you want to proceed with copying the files?") {
+            print_status("File copying cancelled.");
+            std::process::exit(0);
+        }
+
+        match copy_files(repo_path, &config, &selection) {
+            Ok(deployed) => record_files(deployed),
+            Err(e) => {
+                print_error(format!("Failed to copy files: {}", e));
+                std::process::exit(1);
+            }
+        }
+        failed_hooks += run_hooks(Stage::PostFiles, repo_path, &post_files);
+    }
+
+    // Units may come with the copied files, so services are set up last
+    let failed_services = if installed_packages { apply_services(&selected_packages) } else { 0 };
+    let failed_settings = if system { apply_system_settings(&config) } else { 0 };
+
+    if failed_hooks > 0 || failed_services > 0 || failed_settings > 0 {
+        println!();
+        if failed_hooks > 0 {
+            print_error(format!("Installation finished, but {} hook(s) failed", failed_hooks));
+        }
+        if failed_services > 0 {
+            print_error(format!("Installation finished, but {} service(s) could not be set up", failed_services));
+        }
+        if failed_settings > 0 {
+            print_error(format!("Installation finished, but {} system setting(s) could not be applied", failed_settings));
+        }
+        std::process::exit(1);
+    }
+    print_success("Dotfiles installation completed successfully!");
+}
+
+fn run_check(path: &Path) {
+    print_step("Checking configuration");
+
+    let report = match check_repo(path) {
+        Ok(report) => report,
+        Err(e) => {
+            print_error(e);
+            std::process::exit(1);
+        }
+    };
+
+    report.print();
+
+    let errors = report.count(Severity::Error);
+    let warnings = report.count(Severity::Warning);
+    if errors > 0 {
+        print_error(format!("Found {} error(s) and {} warning(s)", errors, warnings));
+        std::process::exit(1);
+    } else if warnings > 0 {
+        print_warning(format!("Configuration is valid with {} warning(s)", warnings));
+    } else {
+        print_success(format!("{} is valid", report.file.display()));
+    }
+}
+
+fn run_migrate(path: &Path, dry_run: bool) {
+    print_step("Migrating configuration");
+
+    let config_path = match find_config(path) {
+        Ok(config_path) => config_path,
+        Err(e) => {
+            print_error(e);
+            std::process::exit(1);
+        }
+    };
+    let format = ConfigFormat::from_path(&config_path).unwrap_or(ConfigFormat::Yaml);
+
+    let source = match std::fs::read_to_string(&config_path) {
+        Ok(source) => source,
+        Err(e) => {
+            print_error(format!("Failed to read {}: {}", config_path.display(), e));
+            std::process::exit(1);
+        }
+    };
+
+    let upgraded = match migrate::upgrade_source(&source, format) {
+        Ok(None) => {
+            print_success(format!(
+                "{} is already at format version {}",
+                config_path.display(),
+                migrate::CURRENT_VERSION
+            ));
+            return;
+        }
+        Ok(Some(upgrade)) => {
+            print_status(format!(
+                "Upgrading from format version {} to {}:",
+                upgrade.from_version,
+                migrate::CURRENT_VERSION
+            ));
+            for step in migrate::pending_steps(upgrade.from_version) {
+                print_status(format!("    • {}", step));
+            }
+            upgrade.source
+        }
+        Err(e) => {
+            print_error(format!("Failed to migrate {}: {}", config_path.display(), e));
+            std::process::exit(1);
+        }
+    };
+
+    println!();
+    print_diff(&source, &upgraded);
+    println!();
+
+    if dry_run {
+        print_status("Dry run, no changes were written.");
+        return;
+    }
+
+    if !confirm("Do you want to apply these changes?") {
+        print_status("Migration cancelled.");
+        return;
+    }
+
+    if let Err(e) = std::fs::write(&config_path, upgraded) {
+        print_error(format!("Failed to write {}: {}", config_path.display(), e));
+        std::process::exit(1);
+    }
+    print_success(format!("Migrated {}", config_path.display()));
+}
+
+fn run_convert(path: &Path, to: ConfigFormat, stdout: bool) {
+    let source_path = match find_config(path) {
+        Ok(source_path) => source_path,
+        Err(e) => {
+            print_error(e);
+            std::process::exit(1);
+        }
+    };
+
+    let config = match DottyConfig::from_file(&source_path) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(format!("Failed to read configuration: {}", e));
+            std::process::exit(1);
+        }
+    };
+
+    let converted = match format::serialize(to, &config) {
+        Ok(converted) => converted,
+        Err(e) => {
+            print_error(format!("Failed to convert configuration to {}: {}", to, e));
+            std::process::exit(1);
+        }
+    };
+
+    if stdout {
+        print!("{}", converted);
+        return;
+    }
+
+    print_step(format!("Converting configuration to {}", to));
+
+    if ConfigFormat::from_path(&source_path) == Some(to) {
+        print_success(format!("{} is already in {} format", source_path.display(), to));
+        return;
+    }
+
+    let target_path = path.join(to.file_name());
+    if target_path.exists() {
+        print_error(format!("{} already exists", target_path.display()));
+        std::process::exit(1);
+    }
+
+    print_status(format!("{} will be replaced by {}", source_path.display(), target_path.display()));
+    print_warning("Comments are not carried over to the new file");
+    println!();
+
+    if !confirm("Do you want to proceed with the conversion?") {
+        print_status("Conversion cancelled.");
+        return;
+    }
+
+    if let Err(e) = std::fs::write(&target_path, converted) {
+        print_error(format!("Failed to write {}: {}", target_path.display(), e));
+        std::process::exit(1);
+    }
+    if let Err(e) = std::fs::remove_file(&source_path) {
+        print_error(format!("Failed to remove {}: {}", source_path.display(), e));
+        std::process::exit(1);
+    }
+
+    print_success(format!("Converted {} to {}", source_path.display(), target_path.display()));
+}
+
+/// Names the secrets a templated mapping uses, never their values
+fn template_note(repo_path: &Path, mapping: &FileMapping) -> String {
+    if !mapping.template || mapping.encrypted {
+        // Encrypted templates would have to be decrypted just to list their secrets
+        return String::new();
+    }
+    let Ok(template) = std::fs::read_to_string(mapping.source_path(repo_path)) else {
+        return String::new();
+    };
+    match referenced_secrets(&template).as_slice() {
+        [] => String::new(),
+        names => format!(" (secrets: {})", names.join(", ")),
+    }
+}
+
+/// Prints how the destination of a single file mapping differs from what would be deployed
+fn print_mapping_diff(repo_path: &Path, mapping: &FileMapping, reader: &SourceReader) {
+    let source = mapping.source_path(repo_path);
+    if source.is_dir() {
+        return;
+    }
+    if mapping.encrypted {
+        print_list_item("Not showing the diff of a decrypted file");
+        return;
+    }
+
+    let expected = if mapping.template { reader.read(mapping, &source) } else { std::fs::read(&source).map_err(Into::into) };
+    let (expected, current) = match (expected, std::fs::read(mapping.destination_path())) {
+        (Ok(expected), Ok(current)) => (expected, current),
+        _ => return,
+    };
+    if std::str::from_utf8(&expected).is_err() || std::str::from_utf8(&current).is_err() {
+        print_list_item("Binary files differ");
+        return;
+    }
+    // The current file is masked too, secrets it holds are shown the same way
+    print_diff(
+        &process::mask(&String::from_utf8_lossy(&current)),
+        &process::mask(&String::from_utf8_lossy(&expected)),
+    );
+}
+
+fn run_status(path: &Path, diff: bool) {
+    print_step("Checking deployed files");
+
+    let config = match DottyConfig::from_path(path) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(format!("Failed to read configuration: {}", e));
+            std::process::exit(1);
+        }
+    };
+
+    let reader = SourceReader::new(&config);
+    let mut changed = 0;
+    for package in config.packages.iter().filter(|p| !p.files.is_empty()) {
+        print_subsection(&package.name);
+
+        for mapping in &package.files {
+            let label = format!("{} → {}", mapping.source, mapping.destination);
+            match mapping_status(path, mapping, &reader) {
+                Ok(status) => {
+                    let message = format!("{} ({})", label, status.state.label());
+                    match status.state {
+                        State::InSync => print_success(message),
+                        _ => {
+                            changed += 1;
+                            print_warning(message);
+                        }
+                    }
+                    for issue in status.issues {
+                        print_list_item(issue);
+                    }
+                    if diff && status.state == State::Modified {
+                        print_mapping_diff(path, mapping, &reader);
+                    }
+                }
+                Err(e) => {
+                    changed += 1;
+                    print_error(format!("{}: {}", label, e));
+                }
+            }
+        }
+    }
+
+    // Services are read from systemd, they are only reported when configured
+    let mut unset = 0;
+    let configured: Vec<&Service> = config.packages.iter().flat_map(|p| &p.services).collect();
+    if !configured.is_empty() {
+        print_step("Checking services");
+        for service in configured {
+            let label = format!("{} ({})", service.unit, service.scope);
+            match services::query(&SystemRunner, service) {
+                Ok(state) => {
+                    let message = format!("{}: {}, {}", label, state.enabled, state.active);
+                    let pending = state.pending(service);
+                    if pending.is_empty() {
+                        print_success(message);
+                    } else {
+                        unset += 1;
+                        let pending: Vec<_> = pending.iter().map(ToString::to_string).collect();
+                        print_warning(format!("{} (needs {})", message, pending.join(", ")));
+                    }
+                }
+                Err(e) => {
+                    unset += 1;
+                    print_error(format!("{}: {}", label, e));
+                }
+            }
+        }
+    }
+
+    println!();
+    if changed == 0 {
+        print_success("All deployed files are in sync");
+    } else {
+        print_warning(format!("{} mapping(s) differ from the repository", changed));
+    }
+    if unset > 0 {
+        print_warning(format!("{} service(s) are not set up as configured", unset));
+    }
+}
+
+fn run_doctor(path: &Path) {
+    print_step("Checking system settings");
+
+    let config = match DottyConfig::from_path(path) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(format!("Failed to read configuration: {}", e));
+            std::process::exit(1);
+        }
+    };
+    if config.system.is_empty() {
+        print_status("The configuration has no system section");
+        return;
+    }
+
+    let states = match inspect_system_settings(&config) {
+        Ok(states) => states,
+        Err(e) => {
+            print_error(format!("Failed to read the system settings: {}", e));
+            std::process::exit(1);
+        }
+    };
+
+    let mut problems = 0;
+    for state in &states {
+        if state.is_applied() {
+            print_success(&state.setting);
+            continue;
+        }
+        problems += 1;
+        match &state.current {
+            Some(current) => print_warning(format!("Expected {}, found {}", state.setting, current)),
+            None => print_warning(format!("Expected {}", state.setting)),
+        }
+        if let Some(problem) = &state.problem {
+            print_list_item(format!("{}, dotty cannot apply it", problem));
+        }
+    }
+
+    println!();
+    if problems == 0 {
+        print_success("The system matches the configuration");
+    } else {
+        print_warning(format!("{} setting(s) differ, run dotty -f {} to apply them", problems, path.display()));
+        std::process::exit(1);
+    }
+}
+
+fn run_prune(path: &Path, remove_files: bool) {
+    print_step("Looking for packages and files that left the configuration");
+
+    let config = match DottyConfig::from_path(path) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(format!("Failed to read configuration: {}", e));
+            std::process::exit(1);
+        }
+    };
+    let mut state = match MachineState::load() {
+        Ok(state) => state,
+        Err(e) => {
+            print_error(e);
+            std::process::exit(1);
+        }
+    };
+
+    prune_packages(&config, &mut state);
+    prune_files(&config, &mut state, remove_files);
+
+    if let Err(e) = state.save() {
+        print_error(e);
+        std::process::exit(1);
+    }
+}
+
+fn prune_packages(config: &DottyConfig, state: &mut MachineState) {
+    let helper = detect_aur_helper();
+    let orphans: Vec<InstalledPackage> = state.orphaned_packages(config).into_iter().cloned().collect();
+
+    // Packages that were uninstalled by hand are only dropped from the state
+    let mut pending: Vec<InstalledPackage> = Vec::new();
+    for source in SOURCE_ORDER.iter().copied() {
+        let packages: Vec<_> = orphans.iter().filter(|p| p.source == source).collect();
+        if packages.is_empty() {
+            continue;
+        }
+        let backend = backend(source, helper, false);
+        let db = match backend.is_available().then(|| backend.query()) {
+            Some(Ok(db)) => db,
+            _ => {
+                print_warning(format!("Could not read installed {} packages, skipping them", source));
+                continue;
+            }
+        };
+        for package in packages {
+            if db.state(&package.name) == PackageState::Missing {
+                state.forget_package(&package.name, source);
+            } else {
+                pending.push(package.clone());
+            }
+        }
+    }
+
+    println!();
+    if pending.is_empty() {
+        print_success("No packages to remove");
+        return;
+    }
+
+    let labels: Vec<_> = pending.iter()
+        .map(|p| if p.name == p.package {
+            format!("{} [{}]", p.name, p.source)
+        } else {
+            format!("{} [{}] (from {})", p.name, p.source, p.package)
+        })
+        .collect();
+    print_package_list("Installed by dotty, no longer in the configuration:", &labels);
+    println!();
+    if !confirm("Do you want to remove these packages?") {
+        print_status("Kept the packages");
+        return;
+    }
+
+    for source in SOURCE_ORDER.iter().copied() {
+        let names: Vec<&str> = pending.iter()
+            .filter(|p| p.source == source)
+            .map(|p| p.name.as_str())
+            .collect();
+        if names.is_empty() {
+            continue;
+        }
+
+        match backend(source, helper, false).remove(&names) {
+            Ok(removed) => {
+                for name in removed {
+                    state.forget_package(&name, source);
+                }
+            }
+            Err(e) => {
+                print_error(format!("Failed to remove {} packages: {}", source, e));
+                print_failure_details(&e);
+            }
+        }
+    }
+}
+
+fn prune_files(config: &DottyConfig, state: &mut MachineState, remove_files: bool) {
+    let orphans: Vec<DeployedFile> = state.orphaned_files(config).into_iter().cloned().collect();
+    let orphans: Vec<DeployedFile> = orphans.into_iter()
+        .filter(|file| {
+            let exists = file.path.symlink_metadata().is_ok();
+            if !exists {
+                state.forget_file(&file.path);
+            }
+            exists
+        })
+        .collect();
+
+    println!();
+    if orphans.is_empty() {
+        print_success("No deployed files to remove");
+        return;
+    }
+
+    let labels: Vec<_> = orphans.iter()
+        .map(|f| format!("{} (from {})", f.path.display(), f.package))
+        .collect();
+    print_package_list("Deployed by dotty, no longer in the configuration:", &labels);
+    if !remove_files {
+        println!();
+        print_status("Run dotty prune --files to remove them as well");
+        return;
+    }
+
+    println!();
+    if !confirm("Do you want to remove these files?") {
+        print_status("Kept the files");
+        return;
+    }
+
+    let paths: Vec<&Path> = orphans.iter().map(|f| f.path.as_path()).collect();
+    remove_deployed(&paths, |path, result| match result {
+        Ok(Removal::Removed) => {
+            print_success(format!("Removed {}", path.display()));
+            state.forget_file(path);
+        }
+        Ok(Removal::Kept) => {
+            print_status(format!("Kept {}, it holds files dotty did not deploy", path.display()));
+            state.forget_file(path);
+        }
+        Err(e) => print_error(format!("Failed to remove {}: {}", path.display(), e)),
+    });
+    if let Some(cache_dir) = dirs::cache_dir() {
+        print_status(format!("Files replaced on install are still in the backups at {}",
+            cache_dir.join("dotty").join("dotty_backups").display()));
+    }
+}
+
 fn main() {
     // Parse command line arguments
     let args = if std::env::args().len() <= 1 {
@@ -131,12 +1384,49 @@ fn main() {
         Args::parse()
     };
 
+    prompt::set_input_mode(args.yes, args.no_input);
+
+    match &args.command {
+        Some(Command::Check { path }) => {
+            run_check(path);
+            return;
+        }
+        Some(Command::Migrate { path, dry_run }) => {
+            run_migrate(path, *dry_run);
+            return;
+        }
+        Some(Command::Convert { path, to, stdout }) => {
+            run_convert(path, *to, *stdout);
+            return;
+        }
+        Some(Command::Status { path, diff }) => {
+            run_status(path, *diff);
+            return;
+        }
+        Some(Command::Doctor { path }) => {
+            run_doctor(path);
+            return;
+        }
+        Some(Command::Prune { path, files }) => {
+            run_prune(path, *files);
+            return;
+        }
+        Some(Command::Schema) => {
+            let schema = DottyConfig::json_schema();
+            println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+            return;
+        }
+        None => {}
+    }
+
+    process::set_verbose(args.verbose);
+
     // Handle cleanup if requested
     if args.clean {
         match BackupManager::clean_backups() {
             Ok(_) => std::process::exit(0),
             Err(e) => {
-                print_error(&format!("Failed to clean backups: {}", e));
+                print_error(format!("Failed to clean backups: {}", e));
                 std::process::exit(1);
             }
         }
@@ -157,7 +1447,7 @@ fn main() {
             let repo = match GitRepo::from_string(repo) {
                 Ok(repo) => repo,
                 Err(e) => {
-                    print_error(&format!("Invalid repository format: {}", e));
+                    print_error(format!("Invalid repository format: {}", e));
                     std::process::exit(1);
                 }
             };
@@ -166,16 +1456,16 @@ fn main() {
             let cache_dir = match ensure_cache_dir() {
                 Ok(dir) => dir,
                 Err(e) => {
-                    print_error(&format!("Failed to create cache directory: {}", e));
+                    print_error(format!("Failed to create cache directory: {}", e));
                     std::process::exit(1);
                 }
             };
 
-            let repo_path = cache_dir.join(&repo.folder_name());
+            let repo_path = cache_dir.join(repo.folder_name());
             
             // Check if repository already exists
             if repo_path.exists() {
-                print_status(&format!(
+                print_status(format!(
                     "Repository already exists at {}",
                     repo_path.display()
                 ));
@@ -187,179 +1477,32 @@ fn main() {
 
                 match clone_result {
                     Ok(_) => {
-                        print_success(&format!(
+                        print_success(format!(
                             "Successfully cloned {} to {}",
                             repo.clone_url(),
                             repo_path.display()
                         ));
                     }
                     Err(e) => {
-                        print_error(&format!("Failed to clone repository: {}", e));
+                        print_error(format!("Failed to clone repository: {}", e));
                         std::process::exit(1);
                     }
                 }
             }
 
-            // Check for dotty.yaml
-            print_status("Checking for dotty.yaml configuration...");
-            match DottyConfig::from_path(&repo_path) {
-                Ok(config) => {
-                    match config.validate() {
-                        Ok(_) => {
-                            print_success("Found valid dotty.yaml configuration");
-                            
-                            // Install packages if there are any
-                            if !config.packages.is_empty() {
-                                if !display_packages_and_confirm(&config) {
-                                    print_status("Installation cancelled.");
-                                    std::process::exit(0);
-                                }
-                                
-                                if let Err(e) = install_packages(&config) {
-                                    print_error(&format!("Failed to install packages: {}", e));
-                                    std::process::exit(1);
-                                }
-                            }
-
-                            // Copy dotfiles if any package has files
-                            let all_files = config.get_all_file_mappings();
-                            if !all_files.is_empty() {
-                                println!();
-                                print_status("Files to be copied:");
-                                for mapping in &all_files {
-                                    let source = repo_path.join(&mapping.source);
-                                    let _expanded_dest = shellexpand::tilde(&mapping.destination);
-                                    
-                                    if source.is_dir() {
-                                        print_status(&format!("    • Directory: {} → {}", 
-                                            mapping.source, mapping.destination));
-                                    } else {
-                                        print_status(&format!("    • File: {} → {}", 
-                                            mapping.source, mapping.destination));
-                                    }
-                                }
-
-                                println!();
-                                print!("Do you want to proceed with copying the files? [Y/n] ");
-                                io::stdout().flush().unwrap();
-
-                                let mut input = String::new();
-                                io::stdin().read_line(&mut input).unwrap();
-                                
-                                let input = input.trim().to_lowercase();
-                                println!();
-
-                                if matches!(input.as_str(), "n" | "no") {
-                                    print_status("File copying cancelled.");
-                                    std::process::exit(0);
-                                }
-
-                                if let Err(e) = copy_files(&repo_path, &config) {
-                                    print_error(&format!("Failed to copy files: {}", e));
-                                    std::process::exit(1);
-                                }
-                            }
-
-                            print_success("Dotfiles installation completed successfully!");
-                        }
-                        Err(e) => {
-                            print_error(&format!("Invalid dotty.yaml configuration: {}", e));
-                            std::process::exit(1);
-                        }
-                    }
-                }
-                Err(e) => {
-                    print_error(&format!("Failed to read dotty.yaml: {}", e));
-                    print_status("You can create a dotty.yaml file manually or use the --init option to generate one.");
-                    std::process::exit(1);
-                }
-            }
+            install_from(&repo_path, &args);
         }
         (None, Some(folder)) => {
             print_step("Installing dotfiles from local folder");
             
-            // Verify folder exists and contains dotty.yaml
-            let config_path = folder.join("dotty.yaml");
-            if !config_path.exists() {
-                print_error("No dotty.yaml found in the specified folder");
-                print_status("Make sure the folder contains a valid dotty.yaml configuration file");
+            // Verify folder contains a configuration file
+            if let Err(e) = find_config(folder) {
+                print_error(e);
+                print_status("Make sure the folder contains a valid dotty.yaml, dotty.toml or dotty.json file");
                 std::process::exit(1);
             }
 
-            // Parse and validate config
-            print_status("Reading configuration...");
-            match DottyConfig::from_path(folder) {
-                Ok(config) => {
-                    match config.validate() {
-                        Ok(_) => {
-                            print_success("Found valid dotty.yaml configuration");
-                            
-                            // Install packages if any
-                            if !config.packages.is_empty() {
-                                if !display_packages_and_confirm(&config) {
-                                    print_status("Installation cancelled.");
-                                    std::process::exit(0);
-                                }
-                                
-                                if let Err(e) = install_packages(&config) {
-                                    print_error(&format!("Failed to install packages: {}", e));
-                                    std::process::exit(1);
-                                }
-                            }
-
-                            // Copy dotfiles if any package has files
-                            let all_files = config.get_all_file_mappings();
-                            if !all_files.is_empty() {
-                                println!();
-                                print_status("Files to be copied:");
-                                for mapping in &all_files {
-                                    let source = folder.join(&mapping.source);
-                                    let _expanded_dest = shellexpand::tilde(&mapping.destination);
-                                    
-                                    if source.is_dir() {
-                                        print_status(&format!("    • Directory: {} → {}", 
-                                            mapping.source, mapping.destination));
-                                    } else {
-                                        print_status(&format!("    • File: {} → {}", 
-                                            mapping.source, mapping.destination));
-                                    }
-                                }
-
-                                println!();
-                                print!("Do you want to proceed with copying the files? [Y/n] ");
-                                io::stdout().flush().unwrap();
-
-                                let mut input = String::new();
-                                io::stdin().read_line(&mut input).unwrap();
-                                
-                                let input = input.trim().to_lowercase();
-                                println!();
-
-                                if matches!(input.as_str(), "n" | "no") {
-                                    print_status("File copying cancelled.");
-                                    std::process::exit(0);
-                                }
-
-                                if let Err(e) = copy_files(folder, &config) {
-                                    print_error(&format!("Failed to copy files: {}", e));
-                                    std::process::exit(1);
-                                }
-                            }
-
-                            print_success("Dotfiles installation completed successfully!");
-                        }
-                        Err(e) => {
-                            print_error(&format!("Invalid dotty.yaml configuration: {}", e));
-                            std::process::exit(1);
-                        }
-                    }
-                }
-                Err(e) => {
-                    print_error(&format!("Failed to read dotty.yaml: {}", e));
-                    print_status("Make sure the folder contains a valid dotty.yaml configuration file");
-                    std::process::exit(1);
-                }
-            }
+            install_from(folder, &args);
         }
         _ => {
             print_error("Invalid combination of arguments. Please use only one of: --repo or --folder");
@@ -368,4 +1511,3 @@ fn main() {
         }
     }
 }
-
@@ -2,9 +2,20 @@ use std::path::{Path, PathBuf};
 use anyhow::Result;
 use chrono::Local;
 use crate::utils::output::*;
+use crate::utils::copy::{copy_entry, copy_tree, CopyOptions, CopyOutcome};
+use crate::utils::encryption::{Decryptor, Format};
+
+/// Backups keep timestamps so they show when the replaced files were last changed
+const BACKUP_OPTIONS: CopyOptions = CopyOptions {
+    skip_existing: false,
+    preserve_times: true,
+    preserve_xattrs: false,
+};
 
 pub struct BackupManager {
     backup_dir: PathBuf,
+    /// Backups mirror the layout of the replaced files below this directory
+    home: PathBuf,
     #[allow(dead_code)]
     timestamp: String,
 }
@@ -24,31 +35,72 @@ impl BackupManager {
 
         Ok(Self {
             backup_dir,
+            home: dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Failed to get home directory"))?,
             timestamp,
         })
     }
 
+    #[cfg(test)]
+    pub(crate) fn in_dir(backup_dir: &Path, home: &Path) -> Self {
+        Self { backup_dir: backup_dir.to_path_buf(), home: home.to_path_buf(), timestamp: String::new() }
+    }
+
     pub fn backup_if_exists(&self, path: &Path) -> Result<bool> {
-        if !path.exists() {
+        // symlink_metadata so dangling symlinks are backed up too
+        let Ok(metadata) = std::fs::symlink_metadata(path) else {
             return Ok(false);
-        }
+        };
 
-        let relative_path = path.strip_prefix(dirs::home_dir().unwrap())?;
+        let relative_path = path.strip_prefix(&self.home)?;
         let backup_path = self.backup_dir.join(relative_path);
 
         if let Some(parent) = backup_path.parent() {
             std::fs::create_dir_all(parent)?;
         }
 
-        if path.is_dir() {
-            print_info(&format!("Creating backup of directory: {}", path.display()));
+        if metadata.is_dir() {
+            print_info(format!("Creating backup of directory: {}", path.display()));
             copy_dir_all(path, &backup_path)?;
         } else {
-            print_info(&format!("Creating backup of file: {}", path.display()));
-            std::fs::copy(path, &backup_path)?;
+            print_info(format!("Creating backup of file: {}", path.display()));
+            copy_entry(path, &backup_path, &BACKUP_OPTIONS)?;
         }
 
-        print_success(&format!("Backup created in: {}", backup_path.display()));
+        print_success(format!("Backup created in: {}", backup_path.display()));
+        Ok(true)
+    }
+
+    /// Backs up `path` encrypted, for files deployed from an encrypted source
+    pub fn backup_encrypted(&self, path: &Path, source: &Path, decryptor: &Decryptor) -> Result<bool> {
+        self.write_encrypted(path, |plaintext| decryptor.encrypt_like(source, plaintext))
+    }
+
+    /// Backs up `path` encrypted for the configured identity
+    pub fn backup_encrypted_for_identity(&self, path: &Path, decryptor: &Decryptor) -> Result<bool> {
+        self.write_encrypted(path, |plaintext| decryptor.encrypt_for_identity(plaintext))
+    }
+
+    fn write_encrypted<F>(&self, path: &Path, encrypt: F) -> Result<bool>
+    where
+        F: FnOnce(&[u8]) -> Result<(Format, Vec<u8>)>,
+    {
+        let Ok(plaintext) = std::fs::read(path) else {
+            return Ok(false);
+        };
+
+        let relative_path = path.strip_prefix(&self.home)?;
+        let (format, encrypted) = encrypt(&plaintext)?;
+        let mut backup_path = self.backup_dir.join(relative_path).into_os_string();
+        backup_path.push(format!(".{}", format.extension()));
+        let backup_path = PathBuf::from(backup_path);
+
+        if let Some(parent) = backup_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        print_info(format!("Creating encrypted backup of file: {}", path.display()));
+        std::fs::write(&backup_path, encrypted)?;
+
+        print_success(format!("Backup created in: {}", backup_path.display()));
         Ok(true)
     }
 
@@ -68,7 +120,7 @@ impl BackupManager {
         }
 
         print_section("Cleaning Backups");
-        print_info(&format!("Removing backup directory: {}", backup_dir.display()));
+        print_info(format!("Removing backup directory: {}", backup_dir.display()));
 
         match std::fs::remove_dir_all(&backup_dir) {
             Ok(_) => {
@@ -76,7 +128,7 @@ impl BackupManager {
                 Ok(())
             },
             Err(e) => {
-                print_error(&format!("Failed to clean backups: {}", e));
+                print_error(format!("Failed to clean backups: {}", e));
                 Err(e.into())
             }
         }
@@ -84,25 +136,20 @@ impl BackupManager {
 }
 
 fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
-    if !dst.exists() {
-        std::fs::create_dir_all(dst)?;
-    }
-
-    for entry in walkdir::WalkDir::new(src) {
-        let entry = entry?;
-        let path = entry.path();
-        let relative = path.strip_prefix(src)?;
-        let target = dst.join(relative);
-
-        if path.is_dir() {
-            std::fs::create_dir_all(&target)?;
-        } else {
-            if let Some(parent) = target.parent() {
-                std::fs::create_dir_all(parent)?;
+    let mut failed = None;
+    copy_tree(src, dst, &BACKUP_OPTIONS, |relative, outcome| match outcome {
+        Ok(CopyOutcome::Special(kind)) => print_warning(format!(
+            "Not backing up {} ({})", src.join(relative).display(), kind)),
+        Ok(_) => {}
+        Err(e) => {
+            if failed.is_none() {
+                failed = Some(anyhow::anyhow!("Failed to back up {}: {}", src.join(relative).display(), e));
             }
-            std::fs::copy(path, target)?;
         }
-    }
+    })?;
 
-    Ok(())
-} 
\ No newline at end of file
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
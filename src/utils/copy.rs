@@ -0,0 +1,156 @@
+use std::fs::{FileType, Metadata};
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use anyhow::{Context, Result};
+use filetime::FileTime;
+use walkdir::WalkDir;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CopyOptions {
+    /// Leave entries that already exist at the target untouched
+    pub skip_existing: bool,
+    /// Copy access and modification times
+    pub preserve_times: bool,
+    /// Copy extended attributes
+    pub preserve_xattrs: bool,
+}
+
+/// What happened to a single entry during a copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyOutcome {
+    File,
+    Symlink,
+    Directory,
+    Skipped,
+    /// FIFOs, sockets and device nodes are never copied, only reported
+    Special(&'static str),
+}
+
+fn special_kind(file_type: &FileType) -> Option<&'static str> {
+    if file_type.is_fifo() {
+        Some("FIFO")
+    } else if file_type.is_socket() {
+        Some("socket")
+    } else if file_type.is_block_device() {
+        Some("block device")
+    } else if file_type.is_char_device() {
+        Some("character device")
+    } else {
+        None
+    }
+}
+
+/// Copies a single entry without following symlinks.
+///
+/// Symlinks are recreated pointing at the same target, even if it does not
+/// exist. Directories are only created, use [`copy_tree`] for their contents.
+pub fn copy_entry(source: &Path, target: &Path, options: &CopyOptions) -> Result<CopyOutcome> {
+    let metadata = std::fs::symlink_metadata(source)
+        .with_context(|| format!("Failed to read {}", source.display()))?;
+    let file_type = metadata.file_type();
+
+    if file_type.is_dir() {
+        std::fs::create_dir_all(target)?;
+        return Ok(CopyOutcome::Directory);
+    }
+
+    if let Some(kind) = special_kind(&file_type) {
+        return Ok(CopyOutcome::Special(kind));
+    }
+
+    let existing = std::fs::symlink_metadata(target).ok();
+    if options.skip_existing && existing.is_some() {
+        return Ok(CopyOutcome::Skipped);
+    }
+
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Writing through an existing symlink would modify whatever it points to
+    if existing.is_some_and(|m| m.file_type().is_symlink() || (file_type.is_symlink() && !m.is_dir())) {
+        std::fs::remove_file(target)?;
+    }
+
+    if file_type.is_symlink() {
+        let link = std::fs::read_link(source)?;
+        std::os::unix::fs::symlink(&link, target)
+            .with_context(|| format!("Failed to create symlink {}", target.display()))?;
+        if options.preserve_times {
+            filetime::set_symlink_file_times(
+                target,
+                FileTime::from_last_access_time(&metadata),
+                FileTime::from_last_modification_time(&metadata),
+            )?;
+        }
+        return Ok(CopyOutcome::Symlink);
+    }
+
+    std::fs::copy(source, target)?;
+    preserve_metadata(source, target, &metadata, options)?;
+    Ok(CopyOutcome::File)
+}
+
+fn preserve_metadata(source: &Path, target: &Path, metadata: &Metadata, options: &CopyOptions) -> Result<()> {
+    if options.preserve_xattrs && xattr::SUPPORTED_PLATFORM {
+        for name in xattr::list(source)? {
+            if let Some(value) = xattr::get(source, &name)? {
+                xattr::set(target, &name, &value).with_context(|| {
+                    format!("Failed to copy attribute {} to {}", name.to_string_lossy(), target.display())
+                })?;
+            }
+        }
+    }
+
+    if options.preserve_times {
+        filetime::set_file_times(
+            target,
+            FileTime::from_last_access_time(metadata),
+            FileTime::from_last_modification_time(metadata),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Recursively copies `source` into `destination` without following symlinks.
+///
+/// `on_entry` is called with the path relative to `source` and the outcome of
+/// every entry, errors on single entries do not stop the copy.
+pub fn copy_tree<F>(source: &Path, destination: &Path, options: &CopyOptions, mut on_entry: F) -> Result<()>
+where
+    F: FnMut(&Path, Result<CopyOutcome>),
+{
+    let mut directories = Vec::new();
+
+    for entry in WalkDir::new(source) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let path = e.path().map(Path::to_path_buf).unwrap_or_default();
+                on_entry(path.strip_prefix(source).unwrap_or(&path), Err(e.into()));
+                continue;
+            }
+        };
+
+        let relative = entry.path().strip_prefix(source)?;
+        let target = destination.join(relative);
+        let outcome = copy_entry(entry.path(), &target, options);
+
+        if let Ok(CopyOutcome::Directory) = outcome {
+            directories.push((entry.path().to_path_buf(), target));
+        }
+        on_entry(relative, outcome);
+    }
+
+    // Directory times change while their contents are written, so set them last, deepest first
+    for (source_dir, target_dir) in directories.iter().rev() {
+        let metadata = std::fs::symlink_metadata(source_dir)?;
+        if let Err(e) = preserve_metadata(source_dir, target_dir, &metadata, options) {
+            let relative = source_dir.strip_prefix(source).unwrap_or(source_dir);
+            on_entry(relative, Err(e));
+        }
+    }
+
+    Ok(())
+}
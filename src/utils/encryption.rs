@@ -0,0 +1,177 @@
+use std::cell::OnceCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use anyhow::{Context, Result};
+use tempfile::TempDir;
+
+/// Tool a source was encrypted with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Age,
+    Gpg,
+}
+
+impl Format {
+    /// Recognizes age by its header, anything else is handed to gpg
+    pub fn detect(contents: &[u8]) -> Self {
+        if contents.starts_with(b"age-encryption.org/") || contents.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----") {
+            Format::Age
+        } else {
+            Format::Gpg
+        }
+    }
+
+    /// Extension of backups encrypted with this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Age => "age",
+            Format::Gpg => "gpg",
+        }
+    }
+}
+
+/// Runs `command` with `input` on stdin and returns its stdout.
+///
+/// Decrypted contents never go through the run log, only the error output is kept.
+fn pipe(command: &mut Command, input: &[u8]) -> Result<Vec<u8>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}, is it installed?", program))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output().with_context(|| format!("Failed to wait for {}", program))?;
+    let _ = writer.join();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{} failed: {}", program, stderr.trim());
+    }
+    Ok(output.stdout)
+}
+
+/// Decrypts `encrypted: true` sources with age or gpg
+pub struct Decryptor {
+    /// age identity file, or a gpg secret key to use instead of the user's keyring
+    identity: Option<PathBuf>,
+    /// Keyring holding only the identity, created the first time gpg needs it
+    gnupg_home: OnceCell<TempDir>,
+}
+
+impl Decryptor {
+    pub fn new(identity: Option<PathBuf>) -> Self {
+        Self { identity, gnupg_home: OnceCell::new() }
+    }
+
+    fn gpg(&self) -> Result<Command> {
+        let mut command = Command::new("gpg");
+        command.args(["--quiet", "--yes"]);
+
+        if let Some(identity) = &self.identity {
+            if self.gnupg_home.get().is_none() {
+                let home = TempDir::new().context("Failed to create a keyring for the identity")?;
+                let imported = Command::new("gpg")
+                    .args(["--batch", "--quiet", "--import"])
+                    .arg(identity)
+                    .env("GNUPGHOME", home.path())
+                    .output()
+                    .context("Failed to run gpg, is it installed?")?;
+                if !imported.status.success() {
+                    anyhow::bail!("Failed to import {}: {}", identity.display(), String::from_utf8_lossy(&imported.stderr).trim());
+                }
+                let _ = self.gnupg_home.set(home);
+            }
+            command.env("GNUPGHOME", self.gnupg_home.get().expect("keyring was created").path());
+        }
+        Ok(command)
+    }
+
+    fn age_identity(&self) -> Result<&Path> {
+        self.identity
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("age encrypted sources need `identity_file` in the settings"))
+    }
+
+    /// Decrypts the source file at `source` into memory
+    pub fn decrypt(&self, source: &Path) -> Result<Vec<u8>> {
+        let encrypted = std::fs::read(source).with_context(|| format!("Failed to read {}", source.display()))?;
+        let plaintext = match Format::detect(&encrypted) {
+            Format::Age => pipe(Command::new("age").arg("--decrypt").arg("-i").arg(self.age_identity()?), &encrypted),
+            Format::Gpg => pipe(self.gpg()?.arg("--decrypt"), &encrypted),
+        };
+        plaintext.with_context(|| format!("Failed to decrypt {}", source.display()))
+    }
+
+    pub fn has_identity(&self) -> bool {
+        self.identity.is_some()
+    }
+
+    fn encrypt_age(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let recipient = Command::new("age-keygen")
+            .arg("-y")
+            .arg(self.age_identity()?)
+            .output()
+            .context("Failed to run age-keygen, is it installed?")?;
+        if !recipient.status.success() {
+            anyhow::bail!("Failed to read the recipient of the age identity");
+        }
+        let recipient = String::from_utf8_lossy(&recipient.stdout).trim().to_string();
+        pipe(Command::new("age").args(["--encrypt", "-r", &recipient]), plaintext)
+    }
+
+    /// Encrypts `plaintext` for the same key that can decrypt `source`
+    pub fn encrypt_like(&self, source: &Path, plaintext: &[u8]) -> Result<(Format, Vec<u8>)> {
+        let encrypted = std::fs::read(source).with_context(|| format!("Failed to read {}", source.display()))?;
+        let format = Format::detect(&encrypted);
+        let output = match format {
+            Format::Age => self.encrypt_age(plaintext)?,
+            Format::Gpg => {
+                let mut command = self.gpg()?;
+                command.args(["--batch", "--encrypt", "--trust-model", "always"]);
+                for key in self.gpg_recipients(&encrypted)? {
+                    command.args(["--recipient", &key]);
+                }
+                pipe(&mut command, plaintext)?
+            }
+        };
+        Ok((format, output))
+    }
+
+    /// Encrypts `plaintext` for the configured identity, an age identity or a gpg secret key
+    pub fn encrypt_for_identity(&self, plaintext: &[u8]) -> Result<(Format, Vec<u8>)> {
+        let identity = self.age_identity()?;
+        let key = std::fs::read(identity).with_context(|| format!("Failed to read {}", identity.display()))?;
+        if key.windows(15).any(|w| w == b"AGE-SECRET-KEY-") {
+            return Ok((Format::Age, self.encrypt_age(plaintext)?));
+        }
+
+        // The keyring gpg() sets up holds only the identity, so it is the default key
+        let encrypted = pipe(
+            self.gpg()?.args(["--batch", "--encrypt", "--trust-model", "always", "--default-recipient-self"]),
+            plaintext,
+        )?;
+        Ok((Format::Gpg, encrypted))
+    }
+
+    /// Key IDs a gpg message is encrypted to, read without decrypting it
+    fn gpg_recipients(&self, encrypted: &[u8]) -> Result<Vec<String>> {
+        let packets = pipe(self.gpg()?.args(["--batch", "--list-only", "--list-packets"]), encrypted)
+            .context("Failed to read which gpg keys the source is encrypted to")?;
+        let keys: Vec<String> = String::from_utf8_lossy(&packets)
+            .lines()
+            .filter(|line| line.starts_with(":pubkey enc packet:"))
+            .filter_map(|line| line.split("keyid ").nth(1))
+            .map(|key| key.trim().to_string())
+            .collect();
+        if keys.is_empty() {
+            anyhow::bail!("Cannot tell which gpg key the source is encrypted to");
+        }
+        Ok(keys)
+    }
+}
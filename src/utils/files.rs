@@ -1,121 +1,348 @@
 use std::path::{Path, PathBuf};
 use anyhow::Result;
-use shellexpand;
-use walkdir::WalkDir;
 use crate::utils::output::*;
 use crate::utils::backup::BackupManager;
-use crate::core::config::DottyConfig;
+use crate::utils::permissions::apply_mapping;
+use crate::utils::copy::{copy_entry, copy_tree, CopyOptions, CopyOutcome};
+use crate::utils::state::{DeployedFile, MachineState};
+use crate::utils::select::Selection;
+use crate::utils::render::SourceReader;
+use crate::core::config::{DottyConfig, FileMapping};
 
-pub fn copy_files(repo_path: &Path, config: &DottyConfig) -> Result<()> {
+/// Copies the selected mappings of the configuration and returns what was deployed
+pub fn copy_files(repo_path: &Path, config: &DottyConfig, selection: &Selection) -> Result<Vec<DeployedFile>> {
     print_section("Copying Files");
 
     let backup_manager = BackupManager::new()?;
-    let all_files = config.get_all_file_mappings();
+    let reader = SourceReader::new(config);
+    let state = MachineState::load().unwrap_or_else(|e| {
+        print_warning(format!("Could not read deployed files, replaced templates are backed up: {}", e));
+        MachineState::default()
+    });
+    let all_files = selection.files(config);
     let total_mappings = all_files.len();
     let mut completed = 0;
+    let mut deployed = Vec::new();
 
-    for mapping in all_files {
+    for (package, mapping) in all_files {
         completed += 1;
-        let source_path = repo_path.join(mapping.source.trim_start_matches('/'));
-        let expanded_dest = shellexpand::tilde(&mapping.destination).into_owned();
-        let destination_path = PathBuf::from(expanded_dest);
+        let source_path = mapping.source_path(repo_path);
+        let destination_path = mapping.destination_path();
 
-        print_subsection(&format!("Processing {}/{}", completed, total_mappings));
-        print_info(&format!("Source: {}", mapping.source));
-        print_info(&format!("Destination: {}", mapping.destination));
+        print_subsection(format!("Processing {}/{}", completed, total_mappings));
+        print_info(format!("Source: {}", mapping.source));
+        print_info(format!("Destination: {}", mapping.destination));
 
         if !source_path.exists() {
-            print_error(&format!("Source path does not exist: {}", source_path.display()));
+            print_error(format!("Source path does not exist: {}", source_path.display()));
             continue;
         }
 
-        if destination_path.exists() {
+        let mut written = Vec::new();
+        if mapping.is_generated() {
+            match deploy_generated(mapping, &source_path, &destination_path, &reader, &backup_manager, &state, &config.copy_options()) {
+                Ok(true) => written.push(destination_path.clone()),
+                Ok(false) => continue,
+                Err(e) => {
+                    print_error(format!("Failed to deploy {}: {}", mapping.source, e));
+                    continue;
+                }
+            }
+        } else if destination_path.exists() {
             match backup_manager.backup_if_exists(&destination_path) {
                 Ok(true) => print_success("Backup created successfully"),
                 Ok(false) => print_info("No backup needed"),
                 Err(e) => {
-                    print_error(&format!("Failed to create backup: {}", e));
+                    print_error(format!("Failed to create backup: {}", e));
                     continue;
                 }
             }
         }
 
-        if source_path.is_dir() {
+        if mapping.is_generated() {
+            // Deployed above
+        } else if source_path.is_dir() {
             if let Err(e) = std::fs::create_dir_all(&destination_path) {
-                print_error(&format!("Failed to create directory: {}", e));
+                print_error(format!("Failed to create directory: {}", e));
                 continue;
             }
-            print_success(&format!("Created directory: {}", destination_path.display()));
+            print_success(format!("Created directory: {}", destination_path.display()));
             
             print_status("Copying contents...");
-            copy_directory(&source_path, &destination_path, config.should_skip_existing())?;
+            written = copy_directory(&source_path, &destination_path, &config.copy_options())?;
         } else {
             if let Some(parent) = destination_path.parent() {
                 if let Err(e) = std::fs::create_dir_all(parent) {
-                    print_error(&format!("Failed to create parent directory: {}", e));
+                    print_error(format!("Failed to create parent directory: {}", e));
                     continue;
                 }
             }
-            copy_file(&source_path, &destination_path, config.should_skip_existing())?;
+            if copy_file(&source_path, &destination_path, &config.copy_options())? {
+                written.push(destination_path.clone());
+            }
+        }
+
+        if mapping.has_attributes() {
+            match apply_mapping(&source_path, &destination_path, mapping) {
+                Ok(_) => print_success("Applied permissions and ownership"),
+                Err(e) => print_error(format!("Failed to apply permissions: {}", e)),
+            }
         }
+
+        // Only what came from the repository is recorded, so prune never removes other files
+        deployed.extend(written.into_iter().map(|path| DeployedFile {
+            path,
+            package: package.name.clone(),
+            rendered: mapping.template,
+        }));
     }
 
     print_separator();
     print_success("All files copied successfully!");
-    print_info(&format!("Backups are stored in: {}", backup_manager.get_backup_dir().display()));
-    Ok(())
+    print_info(format!("Backups are stored in: {}", backup_manager.get_backup_dir().display()));
+    Ok(deployed)
 }
 
-fn copy_directory(source: &Path, destination: &Path, skip_existing: bool) -> Result<()> {
-    for entry in WalkDir::new(source).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        let relative = path.strip_prefix(source)?;
-        let target = destination.join(relative);
-
-        if path.is_file() {
-            if skip_existing && target.exists() {
-                print_list_item(&format!("Skipped: {}", relative.display()));
-                continue;
-            }
-
-            if let Some(parent) = target.parent() {
-                if !parent.exists() {
-                    std::fs::create_dir_all(parent)?;
-                }
-            }
+/// Decrypts or renders `source` into `destination`, readable only by the owner.
+///
+/// A file replaced by a decrypted one is backed up encrypted. An earlier rendering
+/// of a template is not backed up at all since it holds secret values, any other
+/// file it replaces is backed up, encrypted if an identity is configured.
+/// Returns false if the destination was left alone.
+fn deploy_generated(
+    mapping: &FileMapping,
+    source: &Path,
+    destination: &Path,
+    reader: &SourceReader,
+    backup_manager: &BackupManager,
+    state: &MachineState,
+    options: &CopyOptions,
+) -> Result<bool> {
+    if options.skip_existing && destination.exists() {
+        print_list_item(format!("Skipped: {}", destination.display()));
+        return Ok(false);
+    }
 
-            match std::fs::copy(path, &target) {
-                Ok(_) => print_list_item(&format!("Copied: {}", relative.display())),
-                Err(e) => print_error(&format!("Failed to copy {}: {}", relative.display(), e)),
-            }
-        } else if path.is_dir() && !target.exists() {
-            match std::fs::create_dir_all(&target) {
-                Ok(_) => print_list_item(&format!("Created: {}", relative.display())),
-                Err(e) => print_error(&format!("Failed to create directory {}: {}", relative.display(), e)),
-            }
+    let contents = reader.read(mapping, source)?;
+    if destination.exists() {
+        if std::fs::read(destination).is_ok_and(|current| current == contents) {
+            print_info("No backup needed");
+        } else if mapping.encrypted {
+            backup_manager.backup_encrypted(destination, source, reader.decryptor())?;
+        } else if state.is_rendering(destination) {
+            print_info(format!("Not backing up {}, it is an earlier rendering of the template", destination.display()));
+        } else if reader.decryptor().has_identity() {
+            backup_manager.backup_encrypted_for_identity(destination, reader.decryptor())?;
+        } else {
+            backup_manager.backup_if_exists(destination)?;
         }
     }
 
-    Ok(())
+    let parent = destination.parent().unwrap_or(Path::new("/"));
+    std::fs::create_dir_all(parent)?;
+    // Temporary files are created with 0600, so the plaintext is never readable by others
+    let mut file = tempfile::NamedTempFile::new_in(parent)?;
+    std::io::Write::write_all(&mut file, &contents)?;
+    file.persist(destination)?;
+    let action = if mapping.encrypted { "Decrypted" } else { "Rendered" };
+    print_success(format!("{}: {} → {}", action, source.display(), destination.display()));
+    Ok(true)
 }
 
-fn copy_file(source: &Path, destination: &Path, skip_existing: bool) -> Result<()> {
-    if skip_existing && destination.exists() {
-        print_list_item(&format!("Skipped: {}", destination.display()));
-        return Ok(());
-    }
+/// Copies the contents of `source` and returns the paths that were written
+fn copy_directory(source: &Path, destination: &Path, options: &CopyOptions) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    copy_tree(source, destination, options, |relative, outcome| {
+        if matches!(outcome, Ok(CopyOutcome::File | CopyOutcome::Symlink | CopyOutcome::Directory)) {
+            // The root itself has an empty relative path, joining it would add a trailing slash
+            written.push(if relative.as_os_str().is_empty() { destination.to_path_buf() } else { destination.join(relative) });
+        }
+        match outcome {
+            Ok(CopyOutcome::File) => print_list_item(format!("Copied: {}", relative.display())),
+            Ok(CopyOutcome::Symlink) => print_list_item(format!("Linked: {}", relative.display())),
+            Ok(CopyOutcome::Skipped) => print_list_item(format!("Skipped: {}", relative.display())),
+            Ok(CopyOutcome::Special(kind)) => print_warning(format!(
+                "Not copying {} ({}), special files are not supported", relative.display(), kind)),
+            Ok(CopyOutcome::Directory) => {}
+            Err(e) => print_error(format!("Failed to copy {}: {}", relative.display(), e)),
+        }
+    })?;
+    Ok(written)
+}
+
+/// Copies a single file, returns false if the destination was left alone
+fn copy_file(source: &Path, destination: &Path, options: &CopyOptions) -> Result<bool> {
+    // A symlink given directly as a mapping source is deployed as the file it points to
+    let resolved = std::fs::canonicalize(source)?;
 
-    match std::fs::copy(source, destination) {
+    match copy_entry(&resolved, destination, options) {
+        Ok(CopyOutcome::Skipped) => {
+            print_list_item(format!("Skipped: {}", destination.display()));
+            Ok(false)
+        }
+        Ok(CopyOutcome::Special(kind)) => {
+            print_warning(format!("Not copying {} ({}), special files are not supported",
+                source.display(), kind));
+            Ok(false)
+        }
         Ok(_) => {
-            print_success(&format!("Copied: {} → {}", 
+            print_success(format!("Copied: {} → {}", 
                 source.display(), destination.display()));
-            Ok(())
+            Ok(true)
         },
         Err(e) => {
-            print_error(&format!("Failed: {} → {}", 
+            print_error(format!("Failed: {} → {}", 
                 source.display(), destination.display()));
-            print_list_item(&format!("Error: {}", e));
-            Err(e.into())
+            print_list_item(format!("Error: {}", e));
+            Err(e)
         }
     }
-} 
+}
+
+/// What happened to a deployed path when it was removed
+#[derive(Debug, PartialEq)]
+pub enum Removal {
+    Removed,
+    /// A directory that still holds files dotty did not deploy
+    Kept,
+}
+
+/// Removes deployed files, then the deployed directories that are left empty.
+///
+/// `on_entry` is called for every path, errors on single paths do not stop the removal.
+pub fn remove_deployed<F>(paths: &[&Path], mut on_entry: F)
+where
+    F: FnMut(&Path, Result<Removal>),
+{
+    let (mut directories, files): (Vec<&Path>, Vec<&Path>) = paths
+        .iter()
+        .copied()
+        .partition(|path| path.symlink_metadata().is_ok_and(|m| m.is_dir()));
+
+    for file in files {
+        on_entry(file, std::fs::remove_file(file).map(|_| Removal::Removed).map_err(Into::into));
+    }
+
+    // Deepest first, so a directory is empty once its subdirectories are gone
+    directories.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in directories {
+        let result = std::fs::read_dir(dir).map_err(anyhow::Error::from).and_then(|mut entries| {
+            if entries.next().is_some() {
+                return Ok(Removal::Kept);
+            }
+            std::fs::remove_dir(dir)?;
+            Ok(Removal::Removed)
+        });
+        on_entry(dir, result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn only_copied_entries_are_recorded() {
+        let repo = tempfile::tempdir().unwrap();
+        let home = tempfile::tempdir().unwrap();
+        let (source, destination) = (repo.path().join("nvim"), home.path().join("nvim"));
+        write(&source.join("init.lua"), "-- repo");
+        write(&source.join("lua/plugins.lua"), "-- repo");
+        write(&destination.join("init.lua"), "-- mine");
+        write(&destination.join("notes.txt"), "mine");
+
+        let options = CopyOptions { skip_existing: true, ..CopyOptions::default() };
+        let mut written = copy_directory(&source, &destination, &options).unwrap();
+        written.sort();
+
+        assert_eq!(written, [destination.clone(), destination.join("lua"), destination.join("lua/plugins.lua")]);
+    }
+
+    #[test]
+    fn prune_keeps_files_it_did_not_deploy() {
+        let repo = tempfile::tempdir().unwrap();
+        let home = tempfile::tempdir().unwrap();
+        let (source, destination) = (repo.path().join("nvim"), home.path().join("nvim"));
+        write(&source.join("init.lua"), "-- repo");
+        write(&source.join("lua/plugins.lua"), "-- repo");
+        write(&destination.join("notes.txt"), "mine");
+
+        let written = copy_directory(&source, &destination, &CopyOptions::default()).unwrap();
+        let paths: Vec<&Path> = written.iter().map(PathBuf::as_path).collect();
+        let mut outcomes = Vec::new();
+        remove_deployed(&paths, |path, result| outcomes.push((path.to_path_buf(), result.unwrap())));
+
+        assert!(outcomes.contains(&(destination.join("init.lua"), Removal::Removed)));
+        assert!(outcomes.contains(&(destination.join("lua/plugins.lua"), Removal::Removed)));
+        assert!(outcomes.contains(&(destination.join("lua"), Removal::Removed)));
+        assert_eq!(outcomes.last(), Some(&(destination.clone(), Removal::Kept)));
+        assert_eq!(std::fs::read_to_string(destination.join("notes.txt")).unwrap(), "mine");
+        assert!(!destination.join("lua").exists());
+    }
+
+    struct TemplateFixture {
+        _dir: tempfile::TempDir,
+        source: PathBuf,
+        destination: PathBuf,
+        backups: PathBuf,
+        home: PathBuf,
+        config: DottyConfig,
+        mapping: FileMapping,
+    }
+
+    fn template_fixture() -> TemplateFixture {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        let source = dir.path().join("repo/gitconfig");
+        let destination = home.join(".gitconfig");
+        write(&source, "[user]\n");
+        write(&destination, "[user]\n  token = old\n");
+        let mapping = serde_yaml::from_str(&format!(
+            "source: gitconfig\ndestination: {}\ntemplate: true\n",
+            destination.display()
+        )).unwrap();
+        TemplateFixture {
+            backups: dir.path().join("backups"),
+            config: serde_yaml::from_str("packages: []\n").unwrap(),
+            _dir: dir,
+            source,
+            destination,
+            home,
+            mapping,
+        }
+    }
+
+    #[test]
+    fn earlier_renderings_are_not_backed_up() {
+        let t = template_fixture();
+        let mut state = MachineState::default();
+        state.record_file(DeployedFile { path: t.destination.clone(), package: "git".to_string(), rendered: true });
+        let backups = BackupManager::in_dir(&t.backups, &t.home);
+
+        let deployed = deploy_generated(&t.mapping, &t.source, &t.destination, &SourceReader::new(&t.config),
+            &backups, &state, &CopyOptions::default()).unwrap();
+
+        assert!(deployed);
+        assert_eq!(std::fs::read_to_string(&t.destination).unwrap(), "[user]\n");
+        assert!(!t.backups.join(".gitconfig").exists());
+    }
+
+    #[test]
+    fn other_files_replaced_by_a_template_are_backed_up() {
+        let t = template_fixture();
+        let mut state = MachineState::default();
+        state.record_file(DeployedFile { path: t.destination.clone(), package: "git".to_string(), rendered: false });
+        let backups = BackupManager::in_dir(&t.backups, &t.home);
+
+        deploy_generated(&t.mapping, &t.source, &t.destination, &SourceReader::new(&t.config),
+            &backups, &state, &CopyOptions::default()).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&t.destination).unwrap(), "[user]\n");
+        assert_eq!(std::fs::read_to_string(t.backups.join(".gitconfig")).unwrap(), "[user]\n  token = old\n");
+    }
+}
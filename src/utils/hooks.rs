@@ -0,0 +1,101 @@
+use std::path::Path;
+use std::process::Command;
+use crate::core::config::{Hooks, Package};
+use crate::utils::{
+    output::{print_error, print_section, print_success},
+    process::{self, print_failure_details},
+    spinner::with_spinner,
+};
+
+/// Point of the installation a hook runs at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    PreInstall,
+    PostInstall,
+    PostFiles,
+}
+
+impl Stage {
+    /// Name of the stage as written in the configuration
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::PreInstall => "pre_install",
+            Stage::PostInstall => "post_install",
+            Stage::PostFiles => "post_files",
+        }
+    }
+
+    fn commands<'a>(&self, hooks: &'a Hooks) -> &'a [String] {
+        match self {
+            Stage::PreInstall => &hooks.pre_install,
+            Stage::PostInstall => &hooks.post_install,
+            Stage::PostFiles => &hooks.post_files,
+        }
+    }
+}
+
+/// A hook command, with the package it belongs to unless it is configuration wide
+pub struct Hook<'a> {
+    pub package: Option<&'a str>,
+    pub command: &'a str,
+}
+
+impl Hook<'_> {
+    pub fn label(&self) -> String {
+        match self.package {
+            Some(package) => format!("{}: {}", package, self.command),
+            None => self.command.to_string(),
+        }
+    }
+}
+
+/// The `stage` hooks of the configuration and of `packages` in the order they run.
+///
+/// Configuration wide hooks run before the package hooks of `pre_install`
+/// and after them otherwise.
+pub fn hooks_for<'a>(stage: Stage, global: &'a Hooks, packages: &[&'a Package]) -> Vec<Hook<'a>> {
+    let package_hooks = packages.iter().flat_map(|p| {
+        stage.commands(&p.hooks).iter().map(move |c| Hook { package: Some(p.name.as_str()), command: c })
+    });
+    let global_hooks = stage.commands(global).iter().map(|c| Hook { package: None, command: c });
+    match stage {
+        Stage::PreInstall => global_hooks.chain(package_hooks).collect(),
+        _ => package_hooks.chain(global_hooks).collect(),
+    }
+}
+
+/// Runs `hooks` one after another and returns how many failed.
+///
+/// Each hook runs with `sh -c` in the repository, with `DOTTY_REPO`, `DOTTY_HOOK`
+/// and, for package hooks, `DOTTY_PACKAGE` set. A failed hook does not stop the others.
+pub fn run_hooks(stage: Stage, repo_path: &Path, hooks: &[Hook]) -> usize {
+    if hooks.is_empty() {
+        return 0;
+    }
+
+    print_section(format!("Running {} hooks", stage.name()));
+    let repo_path = std::fs::canonicalize(repo_path).unwrap_or_else(|_| repo_path.to_path_buf());
+    let mut failed = 0;
+    for hook in hooks {
+        let label = hook.label();
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(hook.command)
+            .current_dir(&repo_path)
+            .env("DOTTY_REPO", &repo_path)
+            .env("DOTTY_HOOK", stage.name());
+        if let Some(package) = hook.package {
+            command.env("DOTTY_PACKAGE", package);
+        }
+
+        match with_spinner(&format!("{}...", label), || process::run(&mut command)) {
+            Ok(()) => print_success(label),
+            Err(e) => {
+                failed += 1;
+                print_error(format!("{} failed: {}", label, e));
+                print_failure_details(&e);
+            }
+        }
+    }
+    failed
+}
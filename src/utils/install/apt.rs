@@ -0,0 +1,106 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::{Context, Result};
+use crate::utils::install::{
+    backend::{ask_for_sudo_password, install_batch, remove_each, CommandRunner, PackageBackend},
+    InstallReport, LocalDatabase,
+};
+
+/// Installs packages from Debian and Ubuntu repositories
+pub struct AptBackend<'a> {
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> AptBackend<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self { runner }
+    }
+}
+
+impl PackageBackend for AptBackend<'_> {
+    fn name(&self) -> String {
+        "apt repositories".to_string()
+    }
+
+    fn is_available(&self) -> bool {
+        self.runner.has_command("apt-get")
+    }
+
+    fn query(&self) -> Result<LocalDatabase> {
+        let output = self.runner.output("dpkg-query", &["-W", "-f=${Package} ${db:Status-Status} ${Version}\\n"])?;
+        if !output.success {
+            anyhow::bail!("Failed to query installed packages");
+        }
+
+        // Removed packages keep their configuration and stay listed as `config-files`
+        let versions: HashMap<String, String> = output.stdout
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                Some((fields.next()?, fields.next()?, fields.next().unwrap_or_default()))
+            })
+            .filter(|(_, status, _)| *status == "installed")
+            .map(|(name, _, version)| (name.to_string(), version.to_string()))
+            .collect();
+        let installed = versions.keys().cloned().collect();
+
+        // Lines look like `name/suite version arch [upgradable from: old]`
+        let upgradable = self.runner.output("apt", &["list", "--upgradable"])?;
+        let outdated: HashSet<String> = upgradable.stdout
+            .lines()
+            .filter_map(|line| line.split_once('/'))
+            .map(|(name, _)| name.to_string())
+            .collect();
+
+        Ok(LocalDatabase::new(installed, outdated).with_versions(versions))
+    }
+
+    fn install(&self, packages: &[&str]) -> Result<InstallReport> {
+        if packages.is_empty() {
+            return Ok(InstallReport::default());
+        }
+
+        ask_for_sudo_password(self.runner)?;
+        Ok(install_batch(packages, |packages| {
+            let mut args = vec!["DEBIAN_FRONTEND=noninteractive", "apt-get", "install", "-y"];
+            args.extend_from_slice(packages);
+            self.runner
+                .run("sudo", &args)
+                .with_context(|| format!("Failed to install {}", packages.join(", ")))
+        }))
+    }
+
+    fn remove(&self, packages: &[&str]) -> Result<Vec<String>> {
+        if packages.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        ask_for_sudo_password(self.runner)?;
+        Ok(remove_each(packages, |package| {
+            self.runner
+                .run("sudo", &["DEBIAN_FRONTEND=noninteractive", "apt-get", "remove", "-y", package])
+                .with_context(|| format!("Failed to remove {}", package))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::{backend::fake::FakeRunner, PackageState};
+
+    #[test]
+    fn query_skips_removed_packages_and_reads_upgrades() {
+        let runner = FakeRunner::new()
+            .with_output(
+                "dpkg-query -W -f=${Package} ${db:Status-Status} ${Version}\\n",
+                "git installed 1:2.43.0-1\nvim config-files 2:9.1\ncurl installed 8.5.0-2\n",
+            )
+            .with_output("apt list --upgradable", "Listing...\ncurl/noble-updates 8.5.0-3 amd64 [upgradable from: 8.5.0-2]\n");
+        let db = AptBackend::new(&runner).query().unwrap();
+
+        assert_eq!(db.state("git"), PackageState::Installed);
+        assert_eq!(db.state("vim"), PackageState::Missing);
+        assert_eq!(db.state("curl"), PackageState::Outdated);
+        assert_eq!(db.version("git"), Some("1:2.43.0-1"));
+    }
+}
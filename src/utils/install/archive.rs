@@ -0,0 +1,238 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{Context, Result};
+use crate::core::version;
+use crate::utils::install::{
+    backend::{ask_for_sudo_password, install_each, CommandRunner},
+    InstallReport,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Returns `pkgver-pkgrel` if `file` is a package of `name` for this machine
+fn package_file_version(file: &str, name: &str) -> Option<String> {
+    let (stem, compression) = file.split_once(".pkg.tar")?;
+    if compression.ends_with(".sig") {
+        return None;
+    }
+
+    // `name-pkgver-pkgrel-arch`, only the name may contain dashes
+    let fields: Vec<&str> = stem.strip_prefix(name)?.strip_prefix('-')?.split('-').collect();
+    let [pkgver, pkgrel, arch] = fields.as_slice() else {
+        return None;
+    };
+    (*arch == "any" || *arch == std::env::consts::ARCH).then(|| format!("{}-{}", pkgver, pkgrel))
+}
+
+/// Picks the newest file of `names` whose version is `version`
+fn best_match<'a, I>(names: I, package: &str, version: &str) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    names
+        .into_iter()
+        .filter_map(|file| package_file_version(file, package).map(|v| (file, v)))
+        .filter(|(_, v)| version::compare(v, version).is_eq())
+        .max_by(|a, b| version::compare(&a.1, &b.1))
+        .map(|(file, _)| file.to_string())
+}
+
+fn list_dir(dir: &Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Finds packages of an exact version in a package cache or an Arch Linux Archive mirror
+pub struct PackageArchive {
+    cache: PathBuf,
+    /// URL or local directory with the archive's `packages/<letter>/<name>/` layout
+    mirror: String,
+    download_dir: PathBuf,
+}
+
+impl PackageArchive {
+    pub fn new(cache: PathBuf, mirror: String) -> Self {
+        let download_dir = dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("dotty")
+            .join("archive");
+        Self { cache, mirror: mirror.trim_end_matches('/').to_string(), download_dir }
+    }
+
+    /// Path of the package file for `name` at `version`, downloading it if needed
+    pub fn find(&self, name: &str, version: &str) -> Result<PathBuf> {
+        for dir in [&self.cache, &self.download_dir] {
+            if let Some(file) = best_match(list_dir(dir).iter().map(String::as_str), name, version) {
+                return Ok(dir.join(file));
+            }
+        }
+
+        let first = name.chars().next().unwrap_or_default();
+        if self.mirror.starts_with("http://") || self.mirror.starts_with("https://") {
+            let index_url = format!("{}/packages/{}/{}/", self.mirror, first, name);
+            let index = ureq::get(&index_url)
+                .timeout(TIMEOUT)
+                .call()
+                .with_context(|| format!("Failed to list {}", index_url))?
+                .into_string()?;
+
+            // The archive serves a plain directory listing
+            let links = index.split("href=\"").skip(1).filter_map(|rest| rest.split('"').next());
+            let file = best_match(links, name, version).ok_or_else(|| not_found(name, version, &index_url))?;
+            self.download(&format!("{}{}", index_url, file), &file)
+        } else {
+            let nested = Path::new(&self.mirror).join("packages").join(first.to_string()).join(name);
+            let dir = if nested.is_dir() { nested } else { PathBuf::from(&self.mirror) };
+            let file = best_match(list_dir(&dir).iter().map(String::as_str), name, version)
+                .ok_or_else(|| not_found(name, version, &dir.display().to_string()))?;
+            Ok(dir.join(file))
+        }
+    }
+
+    fn download(&self, url: &str, file: &str) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.download_dir)?;
+        let path = self.download_dir.join(file);
+        let partial = self.download_dir.join(format!("{}.part", file));
+
+        let response = ureq::get(url)
+            .timeout(TIMEOUT)
+            .call()
+            .with_context(|| format!("Failed to download {}", url))?;
+        let mut output = std::fs::File::create(&partial)?;
+        std::io::copy(&mut response.into_reader(), &mut output)
+            .with_context(|| format!("Failed to download {}", url))?;
+        std::fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// Installs each `(name, version)` pair from its package file with `pacman -U`
+    pub fn install(&self, runner: &dyn CommandRunner, packages: &[(&str, &str)]) -> Result<InstallReport> {
+        if packages.is_empty() {
+            return Ok(InstallReport::default());
+        }
+
+        ask_for_sudo_password(runner)?;
+        let labels: Vec<String> = packages.iter().map(|(name, version)| format!("{}={}", name, version)).collect();
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        let mut report = install_each(&labels, |label| {
+            let (name, version) = label.split_once('=').unwrap_or((label, ""));
+            let file = self.find(name, version)?;
+            let file = file.to_string_lossy();
+            runner
+                .run("sudo", &["pacman", "-U", "--needed", "--noconfirm", &file])
+                .with_context(|| format!("Failed to install {}", label))
+        });
+
+        // Report plain names so they match the resolved targets
+        for list in [&mut report.installed, &mut report.failed] {
+            for label in list.iter_mut() {
+                if let Some((name, _)) = label.split_once('=') {
+                    *label = name.to_string();
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn not_found(name: &str, version: &str, location: &str) -> anyhow::Error {
+    anyhow::anyhow!("{} {} was found neither in the package cache nor in {}", name, version, location)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use crate::utils::install::backend::fake::FakeRunner;
+
+    const ARCH: &str = std::env::consts::ARCH;
+
+    fn touch(dir: &Path, file: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(file), b"").unwrap();
+    }
+
+    /// Archive over local directories only, so nothing is read from the user's cache
+    fn archive(root: &TempDir, mirror: &Path) -> PackageArchive {
+        PackageArchive {
+            cache: root.path().join("cache"),
+            mirror: mirror.to_string_lossy().into_owned(),
+            download_dir: root.path().join("downloads"),
+        }
+    }
+
+    #[test]
+    fn package_file_versions() {
+        let file = format!("neovim-0.10.2-1-{}.pkg.tar.zst", ARCH);
+        assert_eq!(package_file_version(&file, "neovim").as_deref(), Some("0.10.2-1"));
+        assert_eq!(package_file_version("python-3.12.7-1-any.pkg.tar.zst", "python").as_deref(), Some("3.12.7-1"));
+        assert_eq!(package_file_version("lib32-glibc-2:2.40-1-any.pkg.tar.xz", "lib32-glibc").as_deref(), Some("2:2.40-1"));
+        // Signatures, other packages sharing a prefix and other architectures are not matches
+        assert_eq!(package_file_version(&format!("{}.sig", file), "neovim"), None);
+        assert_eq!(package_file_version(&file.replace("neovim", "neovim-qt"), "neovim"), None);
+        assert_eq!(package_file_version("neovim-0.10.2-1-nonexistent.pkg.tar.zst", "neovim"), None);
+        assert_eq!(package_file_version("neovim-0.10.2-1.tar.gz", "neovim"), None);
+    }
+
+    #[test]
+    fn flat_mirror() {
+        let root = TempDir::new().unwrap();
+        let mirror = root.path().join("mirror");
+        touch(&mirror, &format!("neovim-0.10.1-1-{}.pkg.tar.zst", ARCH));
+        touch(&mirror, &format!("neovim-0.10.2-1-{}.pkg.tar.zst", ARCH));
+
+        let found = archive(&root, &mirror).find("neovim", "0.10.2-1").unwrap();
+        assert_eq!(found, mirror.join(format!("neovim-0.10.2-1-{}.pkg.tar.zst", ARCH)));
+        assert!(archive(&root, &mirror).find("neovim", "0.9.0-1").is_err());
+    }
+
+    #[test]
+    fn archive_layout_mirror() {
+        let root = TempDir::new().unwrap();
+        let mirror = root.path().join("mirror");
+        let dir = mirror.join("packages").join("n").join("neovim");
+        touch(&dir, &format!("neovim-0.10.2-1-{}.pkg.tar.zst", ARCH));
+        touch(&dir, &format!("neovim-0.10.2-2-{}.pkg.tar.zst", ARCH));
+
+        // Without a release, the newest release of the version is picked
+        let found = archive(&root, &mirror).find("neovim", "0.10.2").unwrap();
+        assert_eq!(found, dir.join(format!("neovim-0.10.2-2-{}.pkg.tar.zst", ARCH)));
+    }
+
+    #[test]
+    fn package_cache_comes_first() {
+        let root = TempDir::new().unwrap();
+        let mirror = root.path().join("mirror");
+        let file = format!("neovim-0.10.2-1-{}.pkg.tar.zst", ARCH);
+        touch(&mirror, &file);
+        touch(&root.path().join("cache"), &file);
+
+        let found = archive(&root, &mirror).find("neovim", "0.10.2-1").unwrap();
+        assert_eq!(found, root.path().join("cache").join(file));
+    }
+
+    #[test]
+    fn install_reports_plain_names() {
+        let root = TempDir::new().unwrap();
+        let mirror = root.path().join("mirror");
+        let file = mirror.join(format!("neovim-0.10.2-1-{}.pkg.tar.zst", ARCH));
+        touch(&mirror, &format!("neovim-0.10.2-1-{}.pkg.tar.zst", ARCH));
+
+        let runner = FakeRunner::new();
+        let report = archive(&root, &mirror)
+            .install(&runner, &[("neovim", "0.10.2-1"), ("python", "3.12.7-1")])
+            .unwrap();
+
+        assert_eq!(report.installed, ["neovim"]);
+        assert_eq!(report.failed, ["python"]);
+        assert_eq!(runner.runs(), [
+            "sudo -v".to_string(),
+            format!("sudo pacman -U --needed --noconfirm {}", file.display()),
+        ]);
+    }
+}
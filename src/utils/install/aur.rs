@@ -1,67 +1,45 @@
-use std::process::{Command, Stdio};
 use anyhow::{Context, Result};
 use crate::utils::{
+    install::{backend::{install_each, remove_each, CommandRunner, PackageBackend, SystemRunner}, makepkg::AurBuilder,
+        pacman::PacmanBackend, InstallReport, LocalDatabase},
     system::AURHelper,
     output::print_status,
-    spinner::with_spinner,
 };
 use tempfile::TempDir;
 
-pub struct AURInstaller {
+/// Bootstraps an AUR helper by building it with makepkg in a temporary directory
+pub struct AURInstaller<'a> {
     temp_dir: TempDir,
+    runner: &'a dyn CommandRunner,
 }
 
-impl AURInstaller {
-    pub fn new() -> Result<Self> {
+impl<'a> AURInstaller<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Result<Self> {
         let temp_dir = TempDir::new()
             .context("Failed to create temporary directory")?;
-        Ok(Self { temp_dir })
+        Ok(Self { temp_dir, runner })
     }
 
     pub fn install_base_devel(&self) -> Result<()> {
-        let status = Command::new("sudo")
-            .args(["pacman", "-S", "--needed", "--noconfirm", "base-devel"])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .context("Failed to run pacman")?;
-
-        if !status.success() {
-            anyhow::bail!("Failed to install base-devel");
-        }
-        Ok(())
+        self.runner
+            .run("sudo", &["pacman", "-S", "--needed", "--noconfirm", "base-devel"])
+            .context("Failed to install base-devel")
     }
 
+    /// Clones `url` and builds it inside the temporary directory, the
+    /// working directory of dotty itself is left alone
     pub fn clone_and_install(&self, package: &str, url: &str) -> Result<()> {
         let pkg_path = self.temp_dir.path().join(package);
+        let target = pkg_path.to_string_lossy();
 
-        // Clone the repository
-        Command::new("git")
-            .args(["clone", url])
-            .arg(&pkg_path)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output()
-            .context("Failed to clone repository")?;
-
-        // Change to package directory
-        std::env::set_current_dir(&pkg_path)
-            .context("Failed to change directory")?;
+        self.runner
+            .run("git", &["clone", url, &target])
+            .with_context(|| format!("Failed to clone {}", url))?;
 
         print_status("Installing package...");
-        // Run makepkg
-        let status = Command::new("makepkg")
-            .args(["-si", "--noconfirm"])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .context("Failed to run makepkg")?;
-
-        if !status.success() {
-            anyhow::bail!("Failed to install package");
-        }
-
-        Ok(())
+        self.runner
+            .run_in(Some(&pkg_path), "makepkg", &["-si", "--noconfirm"])
+            .with_context(|| format!("Failed to build {}", package))
     }
 }
 
@@ -75,7 +53,7 @@ pub fn install_aur_helper(package: &str) -> Result<()> {
         .find(|(name, _)| *name == package)
         .ok_or_else(|| anyhow::anyhow!("Unsupported AUR helper: {}", package))?;
 
-    let installer = AURInstaller::new()?;
+    let installer = AURInstaller::new(&SystemRunner)?;
     
     // Install base-devel first
     installer.install_base_devel()?;
@@ -86,28 +64,102 @@ pub fn install_aur_helper(package: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn install_aur_package(helper: AURHelper, package: &str) -> Result<()> {
-    with_spinner(&format!("Installing {} (AUR)...", package), || {
-        let helper_cmd = helper.command()
-            .ok_or_else(|| anyhow::anyhow!("No AUR helper available"))?;
+/// Installs AUR packages through an AUR helper
+pub struct AurBackend<'a> {
+    helper: AURHelper,
+    runner: &'a dyn CommandRunner,
+    review: bool,
+}
+
+impl<'a> AurBackend<'a> {
+    pub fn new(helper: AURHelper, runner: &'a dyn CommandRunner) -> Self {
+        Self { helper, runner, review: false }
+    }
 
-        let status = Command::new(helper_cmd)
-            .args(["-S", "--needed", "--noconfirm", package])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .context("Failed to run AUR helper")?;
+    /// Reviews every PKGBUILD before building. Helpers cannot be stopped
+    /// between their own dependency builds, so dotty builds the packages itself.
+    pub fn with_review(mut self, review: bool) -> Self {
+        self.review = review;
+        self
+    }
+
+    fn builder(&self) -> Option<AurBuilder<'a>> {
+        (self.review || self.helper == AURHelper::Builtin)
+            .then(|| AurBuilder::new(self.runner).with_review(self.review))
+    }
+}
 
-        if !status.success() {
-            anyhow::bail!("Failed to install {}", package);
+impl PackageBackend for AurBackend<'_> {
+    fn name(&self) -> String {
+        match self.helper {
+            _ if self.review => "AUR (built by dotty after review)".to_string(),
+            AURHelper::None => "AUR".to_string(),
+            helper => format!("AUR (using {})", helper.name()),
         }
-        Ok(())
-    })
+    }
+
+    fn is_available(&self) -> bool {
+        match self.builder() {
+            Some(builder) => builder.is_available(),
+            None => self.helper.command().is_some_and(|command| self.runner.has_command(command)),
+        }
+    }
+
+    /// AUR packages show up in the local pacman database like any other package
+    fn query(&self) -> Result<LocalDatabase> {
+        PacmanBackend::new(self.runner).query()
+    }
+
+    fn install(&self, packages: &[&str]) -> Result<InstallReport> {
+        if let Some(builder) = self.builder() {
+            return builder.install(packages);
+        }
+
+        let helper_cmd = self.helper.command()
+            .ok_or_else(|| anyhow::anyhow!("No AUR helper available"))?;
+
+        Ok(install_each(packages, |package| {
+            self.runner
+                .run(helper_cmd, &["-S", "--needed", "--noconfirm", package])
+                .with_context(|| format!("Failed to install {} (AUR)", package))
+        }))
+    }
+
+    /// AUR helpers remove packages like pacman does, the built-in builder leaves it to pacman
+    fn remove(&self, packages: &[&str]) -> Result<Vec<String>> {
+        let Some(helper_cmd) = self.helper.command() else {
+            return PacmanBackend::new(self.runner).remove(packages);
+        };
+
+        Ok(remove_each(packages, |package| {
+            self.runner
+                .run(helper_cmd, &["-Rns", "--noconfirm", package])
+                .with_context(|| format!("Failed to remove {} (AUR)", package))
+        }))
+    }
 }
 
-pub fn install_aur_packages(helper: AURHelper, packages: &[&str]) -> Result<()> {
-    for package in packages {
-        install_aur_package(helper, package)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::{backend::fake::FakeRunner, PackageState};
+
+    #[test]
+    fn query_reads_the_local_pacman_database() {
+        let runner = FakeRunner::new()
+            .with_output("pacman -Q", "paru-bin 2.0.4-1\n")
+            .with_status("pacman -Qqu", false, "");
+        let db = AurBackend::new(AURHelper::Paru, &runner).query().unwrap();
+        assert_eq!(db.state("paru-bin"), PackageState::Installed);
     }
-    Ok(())
-} 
\ No newline at end of file
+
+    #[test]
+    fn helper_installs_each_package_on_its_own() {
+        let runner = FakeRunner::new().failing_on("broken");
+        let report = AurBackend::new(AURHelper::Yay, &runner).install(&["broken", "spotify"]).unwrap();
+
+        assert_eq!(report.installed, ["spotify"]);
+        assert_eq!(report.failed, ["broken"]);
+        assert_eq!(runner.runs(), ["yay -S --needed --noconfirm broken", "yay -S --needed --noconfirm spotify"]);
+    }
+}
@@ -0,0 +1,265 @@
+use std::path::Path;
+use std::process::Command;
+use anyhow::{Context, Result};
+use crate::core::config::Source;
+use crate::utils::{
+    install::{aur::AurBackend, cargo::CargoBackend, flatpak::FlatpakBackend, npm::NpmBackend,
+        pacman::PacmanBackend, pipx::PipxBackend, apt::AptBackend, dnf::DnfBackend, InstallReport, LocalDatabase},
+    output::{print_error, print_success, print_warning},
+    process::{self, print_failure_details},
+    spinner::with_spinner,
+    system::AURHelper,
+};
+
+/// Captured result of a command that is only run to read its output
+pub struct Output {
+    pub success: bool,
+    pub stdout: String,
+}
+
+/// Runs external commands for the backends, so they can be driven by a fake in tests
+pub trait CommandRunner {
+    /// Runs a command in `dir`, or the current directory, with its output
+    /// going to the run log, failing on a non-zero exit status
+    fn run_in(&self, dir: Option<&Path>, program: &str, args: &[&str]) -> Result<()>;
+    /// Runs a command in `dir`, or the current directory, and captures its standard output
+    fn output_in(&self, dir: Option<&Path>, program: &str, args: &[&str]) -> Result<Output>;
+
+    fn run(&self, program: &str, args: &[&str]) -> Result<()> {
+        self.run_in(None, program, args)
+    }
+
+    fn output(&self, program: &str, args: &[&str]) -> Result<Output> {
+        self.output_in(None, program, args)
+    }
+
+    /// Checks if a command exists in the system
+    fn has_command(&self, program: &str) -> bool {
+        self.output("which", &[program]).is_ok_and(|output| output.success)
+    }
+}
+
+/// Runs commands on the actual system
+pub struct SystemRunner;
+
+fn command(dir: Option<&Path>, program: &str, args: &[&str]) -> Command {
+    let mut command = Command::new(program);
+    command.args(args);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    command
+}
+
+impl CommandRunner for SystemRunner {
+    fn run_in(&self, dir: Option<&Path>, program: &str, args: &[&str]) -> Result<()> {
+        process::run(&mut command(dir, program, args))
+    }
+
+    fn output_in(&self, dir: Option<&Path>, program: &str, args: &[&str]) -> Result<Output> {
+        let output = command(dir, program, args)
+            .output()
+            .with_context(|| format!("Failed to run {}", program))?;
+
+        Ok(Output {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        })
+    }
+}
+
+/// A package manager dotty can install packages with
+pub trait PackageBackend {
+    /// Name shown in package lists and messages
+    fn name(&self) -> String;
+    /// Whether the package manager is present on this system
+    fn is_available(&self) -> bool;
+    /// Reads the packages this package manager has installed
+    fn query(&self) -> Result<LocalDatabase>;
+    /// Installs `packages`, a failing package does not stop the others
+    fn install(&self, packages: &[&str]) -> Result<InstallReport>;
+    /// Uninstalls `packages` one by one and returns the ones that were removed
+    fn remove(&self, packages: &[&str]) -> Result<Vec<String>>;
+}
+
+/// Returns the backend that installs packages from `source`
+pub fn backend_for<'a>(source: Source, helper: AURHelper, runner: &'a dyn CommandRunner) -> Box<dyn PackageBackend + 'a> {
+    match source {
+        Source::Pacman => Box::new(PacmanBackend::new(runner)),
+        Source::Aur => Box::new(AurBackend::new(helper, runner)),
+        Source::Apt => Box::new(AptBackend::new(runner)),
+        Source::Dnf => Box::new(DnfBackend::new(runner)),
+        Source::Flatpak => Box::new(FlatpakBackend::new(runner)),
+        Source::Cargo => Box::new(CargoBackend::new(runner)),
+        Source::Pipx => Box::new(PipxBackend::new(runner)),
+        Source::Npm => Box::new(NpmBackend::new(runner)),
+    }
+}
+
+pub(crate) fn ask_for_sudo_password(runner: &dyn CommandRunner) -> Result<()> {
+    runner.run("sudo", &["-v"]).context("Failed to authenticate with sudo")
+}
+
+/// Installs all packages in one transaction.
+///
+/// If the transaction fails, the package manager has not installed anything,
+/// so every package is retried on its own to find out which ones are at fault.
+pub(crate) fn install_batch<F>(packages: &[&str], install: F) -> InstallReport
+where
+    F: Fn(&[&str]) -> Result<()>,
+{
+    let batch = with_spinner(&format!("Installing {} packages...", packages.len()), || install(packages));
+
+    if batch.is_ok() {
+        print_success(format!("Installed {}", packages.join(", ")));
+        return InstallReport {
+            installed: packages.iter().map(|p| p.to_string()).collect(),
+            failed: Vec::new(),
+        };
+    }
+
+    print_warning("Installing all packages at once failed, retrying them one by one");
+    install_each(packages, |package| install(&[package]))
+}
+
+/// Installs packages one command at a time, for package managers without transactions
+pub(crate) fn install_each<F>(packages: &[&str], mut install: F) -> InstallReport
+where
+    F: FnMut(&str) -> Result<()>,
+{
+    let mut report = InstallReport::default();
+    for package in packages {
+        match with_spinner(&format!("Installing {}...", package), || install(package)) {
+            Ok(_) => {
+                print_success(format!("Installed {}", package));
+                report.installed.push(package.to_string());
+            }
+            Err(e) => {
+                print_error(format!("Failed to install {}", package));
+                print_failure_details(&e);
+                report.failed.push(package.to_string());
+            }
+        }
+    }
+    report
+}
+
+/// Removes packages one command at a time, so one that is still needed does not keep the others
+pub(crate) fn remove_each<F>(packages: &[&str], mut remove: F) -> Vec<String>
+where
+    F: FnMut(&str) -> Result<()>,
+{
+    let mut removed = Vec::new();
+    for package in packages {
+        match with_spinner(&format!("Removing {}...", package), || remove(package)) {
+            Ok(_) => {
+                print_success(format!("Removed {}", package));
+                removed.push(package.to_string());
+            }
+            Err(e) => {
+                print_error(format!("Failed to remove {}", package));
+                print_failure_details(&e);
+            }
+        }
+    }
+    removed
+}
+
+/// Command runner for tests that answers from canned output and records every command
+#[cfg(test)]
+pub(crate) mod fake {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use anyhow::Result;
+    use super::{CommandRunner, Output};
+
+    #[derive(Default)]
+    pub(crate) struct FakeRunner {
+        outputs: HashMap<String, Output>,
+        failing: Vec<String>,
+        pub(crate) commands: RefCell<Vec<String>>,
+    }
+
+    impl FakeRunner {
+        pub(crate) fn new() -> Self {
+            Self::default()
+        }
+
+        /// Answers `command`, written as one line, with `stdout` and a successful exit
+        pub(crate) fn with_output(self, command: &str, stdout: &str) -> Self {
+            self.with_status(command, true, stdout)
+        }
+
+        pub(crate) fn with_status(mut self, command: &str, success: bool, stdout: &str) -> Self {
+            self.outputs.insert(command.to_string(), Output { success, stdout: stdout.to_string() });
+            self
+        }
+
+        /// Makes every run with `arg` among its arguments fail
+        pub(crate) fn failing_on(mut self, arg: &str) -> Self {
+            self.failing.push(arg.to_string());
+            self
+        }
+
+        /// Commands that were run, leaving out the ones only read from
+        pub(crate) fn runs(&self) -> Vec<String> {
+            self.commands.borrow().iter().filter_map(|c| c.strip_prefix("run: ")).map(str::to_string).collect()
+        }
+
+        fn line(program: &str, args: &[&str]) -> String {
+            std::iter::once(program).chain(args.iter().copied()).collect::<Vec<_>>().join(" ")
+        }
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn run_in(&self, _dir: Option<&Path>, program: &str, args: &[&str]) -> Result<()> {
+            let line = Self::line(program, args);
+            self.commands.borrow_mut().push(format!("run: {}", line));
+            if args.iter().any(|arg| self.failing.iter().any(|f| f == arg)) {
+                anyhow::bail!("{} failed", line);
+            }
+            Ok(())
+        }
+
+        fn output_in(&self, _dir: Option<&Path>, program: &str, args: &[&str]) -> Result<Output> {
+            let line = Self::line(program, args);
+            self.commands.borrow_mut().push(format!("output: {}", line));
+            Ok(self.outputs.get(&line).map_or(
+                Output { success: false, stdout: String::new() },
+                |output| Output { success: output.success, stdout: output.stdout.clone() },
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fake::FakeRunner;
+
+    #[test]
+    fn batch_success_installs_everything_at_once() {
+        let runner = FakeRunner::new();
+        let report = install_batch(&["a", "b"], |packages| runner.run("install", packages));
+        assert_eq!(report.installed, ["a", "b"]);
+        assert!(report.failed.is_empty());
+        assert_eq!(runner.runs(), ["install a b"]);
+    }
+
+    #[test]
+    fn failed_batch_is_retried_one_by_one() {
+        let runner = FakeRunner::new().failing_on("broken");
+        let report = install_batch(&["a", "broken", "b"], |packages| runner.run("install", packages));
+        assert_eq!(report.installed, ["a", "b"]);
+        assert_eq!(report.failed, ["broken"]);
+        assert_eq!(runner.runs(), ["install a broken b", "install a", "install broken", "install b"]);
+    }
+
+    #[test]
+    fn has_command_asks_which() {
+        let runner = FakeRunner::new().with_output("which pacman", "/usr/bin/pacman\n");
+        assert!(runner.has_command("pacman"));
+        assert!(!runner.has_command("dnf"));
+    }
+}
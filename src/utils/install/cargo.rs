@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use crate::utils::install::{
+    backend::{install_each, remove_each, CommandRunner, PackageBackend},
+    InstallReport, LocalDatabase,
+};
+
+/// Installs crates with `cargo install`
+pub struct CargoBackend<'a> {
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> CargoBackend<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self { runner }
+    }
+}
+
+impl PackageBackend for CargoBackend<'_> {
+    fn name(&self) -> String {
+        "cargo".to_string()
+    }
+
+    fn is_available(&self) -> bool {
+        self.runner.has_command("cargo")
+    }
+
+    fn query(&self) -> Result<LocalDatabase> {
+        let output = self.runner.output("cargo", &["install", "--list"])?;
+        if !output.success {
+            anyhow::bail!("Failed to list installed crates");
+        }
+
+        // Crates are listed as `name v1.2.3:`, followed by their binaries indented
+        let installed = output.stdout
+            .lines()
+            .filter(|line| !line.starts_with(char::is_whitespace))
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect();
+        Ok(LocalDatabase::new(installed, Default::default()))
+    }
+
+    fn install(&self, packages: &[&str]) -> Result<InstallReport> {
+        Ok(install_each(packages, |package| {
+            self.runner
+                .run("cargo", &["install", "--locked", package])
+                .with_context(|| format!("Failed to install {} (cargo)", package))
+        }))
+    }
+
+    fn remove(&self, packages: &[&str]) -> Result<Vec<String>> {
+        Ok(remove_each(packages, |package| {
+            self.runner
+                .run("cargo", &["uninstall", package])
+                .with_context(|| format!("Failed to remove {} (cargo)", package))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::{backend::fake::FakeRunner, PackageState};
+
+    #[test]
+    fn query_ignores_the_listed_binaries() {
+        let runner = FakeRunner::new()
+            .with_output("cargo install --list", "bat v0.24.0:\n    bat\nripgrep v14.1.1:\n    rg\n");
+        let db = CargoBackend::new(&runner).query().unwrap();
+
+        assert_eq!(db.state("bat"), PackageState::Installed);
+        assert_eq!(db.state("ripgrep"), PackageState::Installed);
+        assert_eq!(db.state("rg"), PackageState::Missing);
+    }
+}
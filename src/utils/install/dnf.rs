@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use crate::utils::install::{
+    backend::{ask_for_sudo_password, install_batch, remove_each, CommandRunner, PackageBackend},
+    parse_package_list, parse_version_list, InstallReport, LocalDatabase,
+};
+
+/// Installs packages from Fedora repositories
+pub struct DnfBackend<'a> {
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> DnfBackend<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self { runner }
+    }
+}
+
+impl PackageBackend for DnfBackend<'_> {
+    fn name(&self) -> String {
+        "dnf repositories".to_string()
+    }
+
+    fn is_available(&self) -> bool {
+        self.runner.has_command("dnf")
+    }
+
+    fn query(&self) -> Result<LocalDatabase> {
+        let output = self.runner.output("rpm", &["-qa", "--queryformat", "%{NAME} %{VERSION}-%{RELEASE}\\n"])?;
+        if !output.success {
+            anyhow::bail!("Failed to query installed packages");
+        }
+
+        // Exits with 100 when updates are available, lines look like `name.arch version repo`
+        let updates = self.runner.output("dnf", &["check-update", "--quiet"])?;
+        let outdated = updates.stdout
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .filter_map(|package| package.rsplit_once('.'))
+            .map(|(name, _)| name.to_string())
+            .collect();
+
+        Ok(LocalDatabase::new(parse_package_list(&output.stdout), outdated)
+            .with_versions(parse_version_list(&output.stdout)))
+    }
+
+    fn install(&self, packages: &[&str]) -> Result<InstallReport> {
+        if packages.is_empty() {
+            return Ok(InstallReport::default());
+        }
+
+        ask_for_sudo_password(self.runner)?;
+        Ok(install_batch(packages, |packages| {
+            let mut args = vec!["dnf", "install", "-y"];
+            args.extend_from_slice(packages);
+            self.runner
+                .run("sudo", &args)
+                .with_context(|| format!("Failed to install {}", packages.join(", ")))
+        }))
+    }
+
+    fn remove(&self, packages: &[&str]) -> Result<Vec<String>> {
+        if packages.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        ask_for_sudo_password(self.runner)?;
+        Ok(remove_each(packages, |package| {
+            self.runner
+                .run("sudo", &["dnf", "remove", "-y", package])
+                .with_context(|| format!("Failed to remove {}", package))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::{backend::fake::FakeRunner, PackageState};
+
+    #[test]
+    fn query_reads_rpm_and_check_update() {
+        let runner = FakeRunner::new()
+            .with_output("rpm -qa --queryformat %{NAME} %{VERSION}-%{RELEASE}\\n", "git 2.47.0-1.fc41\nzsh 5.9-15.fc41\n")
+            // check-update exits with 100 when there are updates
+            .with_status("dnf check-update --quiet", false, "\nzsh.x86_64    5.9-16.fc41    updates\n");
+        let db = DnfBackend::new(&runner).query().unwrap();
+
+        assert_eq!(db.state("git"), PackageState::Installed);
+        assert_eq!(db.state("zsh"), PackageState::Outdated);
+        assert_eq!(db.version("git"), Some("2.47.0-1.fc41"));
+    }
+}
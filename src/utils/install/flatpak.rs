@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use crate::utils::install::{
+    backend::{install_each, remove_each, CommandRunner, PackageBackend},
+    parse_package_list, InstallReport, LocalDatabase,
+};
+
+/// Installs Flatpak applications by their application ID
+pub struct FlatpakBackend<'a> {
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> FlatpakBackend<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self { runner }
+    }
+}
+
+impl PackageBackend for FlatpakBackend<'_> {
+    fn name(&self) -> String {
+        "flatpak".to_string()
+    }
+
+    fn is_available(&self) -> bool {
+        self.runner.has_command("flatpak")
+    }
+
+    fn query(&self) -> Result<LocalDatabase> {
+        let output = self.runner.output("flatpak", &["list", "--app", "--columns=application"])?;
+        if !output.success {
+            anyhow::bail!("Failed to list installed flatpak applications");
+        }
+        Ok(LocalDatabase::new(parse_package_list(&output.stdout), Default::default()))
+    }
+
+    fn install(&self, packages: &[&str]) -> Result<InstallReport> {
+        Ok(install_each(packages, |package| {
+            self.runner
+                .run("flatpak", &["install", "--noninteractive", "-y", package])
+                .with_context(|| format!("Failed to install {} (flatpak)", package))
+        }))
+    }
+
+    fn remove(&self, packages: &[&str]) -> Result<Vec<String>> {
+        Ok(remove_each(packages, |package| {
+            self.runner
+                .run("flatpak", &["uninstall", "--noninteractive", "-y", package])
+                .with_context(|| format!("Failed to remove {} (flatpak)", package))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::{backend::fake::FakeRunner, PackageState};
+
+    #[test]
+    fn query_lists_application_ids() {
+        let runner = FakeRunner::new()
+            .with_output("flatpak list --app --columns=application", "com.spotify.Client\norg.gimp.GIMP\n");
+        let db = FlatpakBackend::new(&runner).query().unwrap();
+
+        assert_eq!(db.state("com.spotify.Client"), PackageState::Installed);
+        assert_eq!(db.state("org.videolan.VLC"), PackageState::Missing);
+    }
+}
@@ -0,0 +1,538 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use crate::utils::{
+    install::{backend::{ask_for_sudo_password, CommandRunner}, review::{review, TrustStore},
+        rpc::{self, AurPackage}, InstallReport},
+    output::{print_error, print_success},
+    process::print_failure_details,
+    spinner::with_spinner,
+};
+
+const AUR_URL: &str = "https://aur.archlinux.org";
+
+/// Strips version constraints such as `>=1.2` from a dependency
+fn dependency_name(dependency: &str) -> &str {
+    dependency
+        .split(['<', '>', '='])
+        .next()
+        .unwrap_or(dependency)
+}
+
+/// A package base to build, with everything it needs from the AUR
+#[derive(Debug, Default)]
+struct Base {
+    /// Packages of this base that are installed, with whether they were asked for
+    packages: Vec<(String, bool)>,
+    /// Other package bases that have to be installed first
+    depends_on: BTreeSet<String>,
+    /// Dependencies of this base available from the official repositories
+    repo_dependencies: BTreeSet<String>,
+}
+
+/// What has to happen to install a set of AUR packages
+#[derive(Debug, Default)]
+pub struct BuildPlan {
+    /// Package bases in build order, dependencies first
+    pub order: Vec<String>,
+    bases: HashMap<String, Base>,
+}
+
+/// Builds AUR packages with makepkg, without an AUR helper.
+///
+/// Clones and built packages are kept in the cache directory, so a package
+/// is only rebuilt when its PKGBUILD produces a new version.
+pub struct AurBuilder<'a> {
+    runner: &'a dyn CommandRunner,
+    /// Looks packages up in the AUR, the RPC interface outside of tests
+    lookup: &'a dyn Fn(&[&str]) -> Result<Vec<AurPackage>>,
+    cache_dir: PathBuf,
+    review: bool,
+}
+
+impl<'a> AurBuilder<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        let cache_dir = dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("dotty")
+            .join("aur");
+        Self { runner, lookup: &rpc::info, cache_dir, review: false }
+    }
+
+    #[cfg(test)]
+    fn with_lookup(
+        runner: &'a dyn CommandRunner,
+        lookup: &'a dyn Fn(&[&str]) -> Result<Vec<AurPackage>>,
+        cache_dir: &Path,
+    ) -> Self {
+        Self { runner, lookup, cache_dir: cache_dir.to_path_buf(), review: false }
+    }
+
+    /// Asks for approval of every PKGBUILD before anything is built
+    pub fn with_review(mut self, review: bool) -> Self {
+        self.review = review;
+        self
+    }
+
+    /// Whether the tools needed to build packages are installed
+    pub fn is_available(&self) -> bool {
+        self.runner.has_command("makepkg") && self.runner.has_command("git")
+    }
+
+    /// Returns the dependencies that are not installed, according to `pacman -T`
+    fn unsatisfied(&self, dependencies: &[String]) -> Result<Vec<String>> {
+        if dependencies.is_empty() {
+            return Ok(Vec::new());
+        }
+        let args: Vec<&str> = std::iter::once("-T").chain(dependencies.iter().map(String::as_str)).collect();
+        // Exits with 127 and lists the missing ones when anything is unsatisfied
+        let output = self.runner.output("pacman", &args)?;
+        Ok(output.stdout.lines().map(str::to_string).collect())
+    }
+
+    /// Checks if a dependency, possibly a virtual one, can be installed by pacman
+    fn in_repos(&self, dependency: &str) -> bool {
+        self.runner
+            .output("pacman", &["-Sp", "--print-format", "%n", dependency])
+            .is_ok_and(|output| output.success)
+    }
+
+    /// Resolves the full AUR dependency chain of `packages`
+    pub fn plan(&self, packages: &[&str]) -> Result<BuildPlan> {
+        let mut plan = BuildPlan::default();
+        let mut known: HashMap<String, AurPackage> = HashMap::new();
+        let mut wanted: HashSet<String> = HashSet::new();
+        let mut repo_dependencies: HashMap<String, Vec<String>> = HashMap::new();
+        let mut pending: Vec<String> = packages.iter().map(|p| p.to_string()).collect();
+
+        while !pending.is_empty() {
+            let names: Vec<&str> = pending.iter().map(String::as_str).collect();
+            let found = (self.lookup)(&names).context("Failed to look up AUR packages")?;
+
+            let missing: Vec<_> = names
+                .iter()
+                .filter(|name| !found.iter().any(|p| p.name == **name))
+                .collect();
+            if let Some(name) = missing.first() {
+                anyhow::bail!("{} was not found in the repositories or the AUR", name);
+            }
+
+            let mut next = Vec::new();
+            for package in found {
+                let dependencies: Vec<String> = package.depends.iter()
+                    .chain(&package.make_depends)
+                    .chain(&package.check_depends)
+                    .cloned()
+                    .collect();
+
+                for dependency in self.unsatisfied(&dependencies)? {
+                    let name = dependency_name(&dependency).to_string();
+                    if self.in_repos(&dependency) {
+                        repo_dependencies.entry(package.name.clone()).or_default().push(name);
+                    } else {
+                        if !known.contains_key(&name) && !next.contains(&name) && !pending.contains(&name) {
+                            next.push(name.clone());
+                        }
+                        wanted.insert(name);
+                    }
+                }
+                known.insert(package.name.clone(), package);
+            }
+            pending = next;
+        }
+
+        // Now that every package is known, link bases to the bases they depend on
+        for package in known.values() {
+            let explicit = packages.contains(&package.name.as_str());
+            let base = plan.bases.entry(package.package_base.clone()).or_default();
+            base.packages.push((package.name.clone(), explicit));
+            base.repo_dependencies.extend(repo_dependencies.get(&package.name).into_iter().flatten().cloned());
+
+            for dependency in package.depends.iter().chain(&package.make_depends).chain(&package.check_depends) {
+                if let Some(dependency) = known.get(dependency_name(dependency)) {
+                    if dependency.package_base != package.package_base && wanted.contains(&dependency.name) {
+                        base.depends_on.insert(dependency.package_base.clone());
+                    }
+                }
+            }
+        }
+
+        plan.order = build_order(&plan.bases)?;
+        Ok(plan)
+    }
+
+    /// Builds and installs `packages` with their dependencies
+    pub fn install(&self, packages: &[&str]) -> Result<InstallReport> {
+        let mut report = InstallReport::default();
+        if packages.is_empty() {
+            return Ok(report);
+        }
+
+        let plan = with_spinner("Resolving AUR dependencies...", || self.plan(packages))?;
+
+        // Everything is downloaded and reviewed before the first build, so
+        // what gets built is exactly what was approved
+        let mut failed_bases: HashSet<&str> = HashSet::new();
+        let mut dirs: HashMap<&str, PathBuf> = HashMap::new();
+        let mut store = if self.review { Some(TrustStore::load(self.cache_dir.join("reviewed"))?) } else { None };
+        for base_name in &plan.order {
+            let fetched = with_spinner(&format!("Downloading {}...", base_name), || self.fetch(base_name));
+            let dir = match fetched {
+                Ok(dir) => dir,
+                Err(e) => {
+                    print_error(format!("{:#}", e));
+                    print_failure_details(&e);
+                    failed_bases.insert(base_name);
+                    continue;
+                }
+            };
+
+            if let Some(store) = &mut store {
+                if !review(self.runner, store, base_name, &dir)? {
+                    failed_bases.insert(base_name);
+                    continue;
+                }
+            }
+            dirs.insert(base_name, dir);
+        }
+
+        // Bases built on a rejected or missing one are dropped too, so nothing
+        // of them is installed, not even their dependencies
+        for base_name in &plan.order {
+            let base = &plan.bases[base_name];
+            if let Some(dependency) = base.depends_on.iter().find(|dep| failed_bases.contains(dep.as_str())) {
+                if dirs.remove(base_name.as_str()).is_some() {
+                    print_error(format!("Not building {}, dependency {} will not be built", base_name, dependency));
+                }
+                failed_bases.insert(base_name);
+            }
+        }
+
+        if dirs.is_empty() {
+            report.failed.extend(packages.iter().map(|p| p.to_string()));
+            return Ok(report);
+        }
+
+        ask_for_sudo_password(self.runner)?;
+
+        let repo_dependencies: BTreeSet<&str> = dirs.keys()
+            .flat_map(|base| &plan.bases[*base].repo_dependencies)
+            .map(String::as_str)
+            .collect();
+        if !repo_dependencies.is_empty() {
+            let mut args = vec!["pacman", "-S", "--needed", "--noconfirm", "--asdeps"];
+            args.extend(repo_dependencies);
+            with_spinner("Installing build dependencies...", || self.runner.run("sudo", &args))
+                .context("Failed to install dependencies from the official repositories")?;
+        }
+
+        for base_name in &plan.order {
+            let base = &plan.bases[base_name];
+            let explicit: Vec<&str> = base.packages.iter()
+                .filter(|(_, explicit)| *explicit)
+                .map(|(name, _)| name.as_str())
+                .collect();
+
+            // Bases that were not downloaded or approved already reported why
+            let Some(dir) = dirs.get(base_name.as_str()) else {
+                report.failed.extend(explicit.iter().map(|p| p.to_string()));
+                continue;
+            };
+
+            let result = match base.depends_on.iter().find(|dep| failed_bases.contains(dep.as_str())) {
+                Some(dependency) => Err(anyhow::anyhow!("Dependency {} was not built", dependency)),
+                None => with_spinner(&format!("Building {}...", base_name), || self.build_and_install(base_name, dir, base)),
+            };
+
+            match result {
+                Ok(_) => {
+                    print_success(format!("Installed {}", base.packages.iter()
+                        .map(|(name, _)| name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")));
+                    report.installed.extend(explicit.iter().map(|p| p.to_string()));
+                }
+                Err(e) => {
+                    print_error(format!("Failed to build {}: {}", base_name, e));
+                    print_failure_details(&e);
+                    failed_bases.insert(base_name);
+                    report.failed.extend(explicit.iter().map(|p| p.to_string()));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Clones or updates the AUR repository of a package base
+    fn fetch(&self, base: &str) -> Result<PathBuf> {
+        let dir = self.cache_dir.join(base);
+        if dir.join(".git").is_dir() {
+            self.runner
+                .run_in(Some(&dir), "git", &["pull", "--ff-only"])
+                .with_context(|| format!("Failed to update {}", base))?;
+        } else {
+            std::fs::create_dir_all(&self.cache_dir)?;
+            let url = format!("{}/{}.git", AUR_URL, base);
+            let target = dir.to_string_lossy();
+            self.runner
+                .run("git", &["clone", &url, &target])
+                .with_context(|| format!("Failed to clone {}", url))?;
+        }
+        Ok(dir)
+    }
+
+    fn build_and_install(&self, base_name: &str, dir: &Path, base: &Base) -> Result<()> {
+        let mut files = self.package_files(dir, base)?;
+        if files.iter().any(|(file, _)| !file.exists()) {
+            self.runner
+                .run_in(Some(dir), "makepkg", &["--noconfirm", "--force"])
+                .with_context(|| format!("Failed to build {}", base_name))?;
+            files = self.package_files(dir, base)?;
+        }
+
+        for explicit in [true, false] {
+            let selected: Vec<String> = files.iter()
+                .filter(|(_, is_explicit)| *is_explicit == explicit)
+                .map(|(file, _)| file.to_string_lossy().into_owned())
+                .collect();
+            if selected.is_empty() {
+                continue;
+            }
+
+            let mut args = vec!["pacman", "-U", "--noconfirm"];
+            if !explicit {
+                args.push("--asdeps");
+            }
+            args.extend(selected.iter().map(String::as_str));
+            self.runner
+                .run("sudo", &args)
+                .with_context(|| format!("Failed to install {}", base_name))?;
+        }
+        Ok(())
+    }
+
+    /// Paths the built packages of `base` end up at, with whether each was asked for
+    fn package_files(&self, dir: &Path, base: &Base) -> Result<Vec<(PathBuf, bool)>> {
+        let output = self.runner.output_in(Some(dir), "makepkg", &["--packagelist"])?;
+        if !output.success {
+            anyhow::bail!("Failed to read the package list of {}", dir.display());
+        }
+
+        let paths: Vec<PathBuf> = output.stdout.lines().map(PathBuf::from).collect();
+        base.packages
+            .iter()
+            .map(|(name, explicit)| {
+                paths.iter()
+                    .find(|path| is_package_file(path, name))
+                    .map(|path| (path.clone(), *explicit))
+                    .ok_or_else(|| anyhow::anyhow!("makepkg does not build {}", name))
+            })
+            .collect()
+    }
+}
+
+/// Package files are named `name-pkgver-pkgrel-arch.pkg.tar.*`, and only
+/// the name may contain dashes
+fn is_package_file(path: &Path, name: &str) -> bool {
+    path.file_name()
+        .and_then(|file| file.to_str())
+        .and_then(|file| file.strip_prefix(name))
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|rest| rest.splitn(4, '-').count() == 3)
+}
+
+/// Orders package bases so every base comes after the ones it depends on
+fn build_order(bases: &HashMap<String, Base>) -> Result<Vec<String>> {
+    fn visit<'a>(
+        name: &'a str,
+        bases: &'a HashMap<String, Base>,
+        visiting: &mut Vec<&'a str>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if order.iter().any(|n| n == name) {
+            return Ok(());
+        }
+        if visiting.contains(&name) {
+            anyhow::bail!("Dependency cycle between AUR packages: {} -> {}", visiting.join(" -> "), name);
+        }
+
+        visiting.push(name);
+        if let Some(base) = bases.get(name) {
+            for dependency in &base.depends_on {
+                visit(dependency, bases, visiting, order)?;
+            }
+        }
+        visiting.pop();
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    // Sorted so the build order does not depend on hash map iteration
+    let mut names: Vec<&String> = bases.keys().collect();
+    names.sort();
+
+    let mut order = Vec::new();
+    for name in names {
+        visit(name, bases, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::backend::fake::FakeRunner;
+
+    fn aur(name: &str, base: &str, depends: &[&str]) -> AurPackage {
+        AurPackage {
+            name: name.to_string(),
+            package_base: base.to_string(),
+            version: "1.0-1".to_string(),
+            depends: depends.iter().map(|d| d.to_string()).collect(),
+            make_depends: Vec::new(),
+            check_depends: Vec::new(),
+        }
+    }
+
+    fn lookup(known: Vec<AurPackage>) -> impl Fn(&[&str]) -> Result<Vec<AurPackage>> {
+        move |names| Ok(known.iter().filter(|p| names.contains(&p.name.as_str())).cloned().collect())
+    }
+
+    fn bases(dependencies: &[(&str, &[&str])]) -> HashMap<String, Base> {
+        dependencies
+            .iter()
+            .map(|(name, depends_on)| {
+                let base = Base {
+                    depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+                    ..Base::default()
+                };
+                (name.to_string(), base)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strips_version_constraints() {
+        assert_eq!(dependency_name("glibc>=2.38"), "glibc");
+        assert_eq!(dependency_name("python-foo=1.0"), "python-foo");
+        assert_eq!(dependency_name("zlib"), "zlib");
+    }
+
+    #[test]
+    fn package_files_do_not_match_longer_names() {
+        let file = Path::new("/cache/foo-bar-1.2.3-1-x86_64.pkg.tar.zst");
+        assert!(is_package_file(file, "foo-bar"));
+        assert!(!is_package_file(file, "foo"));
+        assert!(!is_package_file(Path::new("/cache/foo-1.2.3-1-x86_64.pkg.tar.zst"), "foo-bar"));
+        assert!(is_package_file(Path::new("/cache/foo-1:2.0-3-any.pkg.tar.zst"), "foo"));
+    }
+
+    #[test]
+    fn builds_dependencies_first_in_a_stable_order() {
+        let bases = bases(&[("app", &["lib-b", "lib-a"]), ("lib-a", &["lib-c"]), ("lib-b", &[]), ("lib-c", &[]), ("tool", &[])]);
+        let order = build_order(&bases).unwrap();
+        assert_eq!(order, ["lib-c", "lib-a", "lib-b", "app", "tool"]);
+        for _ in 0..10 {
+            assert_eq!(build_order(&bases).unwrap(), order);
+        }
+    }
+
+    #[test]
+    fn reports_dependency_cycles() {
+        let bases = bases(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+        let error = build_order(&bases).unwrap_err().to_string();
+        assert_eq!(error, "Dependency cycle between AUR packages: a -> b -> c -> a");
+    }
+
+    #[test]
+    fn plan_links_bases_and_splits_repo_dependencies() {
+        let runner = FakeRunner::new()
+            .with_status("pacman -T lib-git cmake", false, "lib-git\ncmake\n")
+            .with_output("pacman -Sp --print-format %n cmake", "cmake\n");
+        let lookup = lookup(vec![aur("app-git", "app-git", &["lib-git", "cmake"]), aur("lib-git", "lib-git", &[])]);
+        let cache = tempfile::tempdir().unwrap();
+        let plan = AurBuilder::with_lookup(&runner, &lookup, cache.path()).plan(&["app-git"]).unwrap();
+
+        assert_eq!(plan.order, ["lib-git", "app-git"]);
+        let app = &plan.bases["app-git"];
+        assert_eq!(app.packages, [("app-git".to_string(), true)]);
+        assert!(app.depends_on.contains("lib-git"));
+        assert!(app.repo_dependencies.contains("cmake"));
+        assert_eq!(plan.bases["lib-git"].packages, [("lib-git".to_string(), false)]);
+    }
+
+    #[test]
+    fn split_packages_of_one_base_are_built_once() {
+        let runner = FakeRunner::new()
+            .with_status("pacman -T foo-libs", false, "foo-libs\n")
+            .with_output(
+                "makepkg --packagelist",
+                "/cache/foo/foo-1.0-1-x86_64.pkg.tar.zst\n/cache/foo/foo-libs-1.0-1-x86_64.pkg.tar.zst\n",
+            );
+        let lookup = lookup(vec![aur("foo", "foo", &["foo-libs"]), aur("foo-libs", "foo", &[])]);
+        let cache = tempfile::tempdir().unwrap();
+        let builder = AurBuilder::with_lookup(&runner, &lookup, cache.path());
+
+        let plan = builder.plan(&["foo"]).unwrap();
+        assert_eq!(plan.order, ["foo"]);
+        let mut packages = plan.bases["foo"].packages.clone();
+        packages.sort();
+        assert_eq!(packages, [("foo".to_string(), true), ("foo-libs".to_string(), false)]);
+        assert!(plan.bases["foo"].depends_on.is_empty());
+
+        let report = builder.install(&["foo"]).unwrap();
+        assert_eq!(report.installed, ["foo"]);
+        let runs = runner.runs();
+        assert_eq!(runs.iter().filter(|r| r.starts_with("makepkg")).count(), 1);
+        assert!(runs.contains(&"sudo pacman -U --noconfirm /cache/foo/foo-1.0-1-x86_64.pkg.tar.zst".to_string()));
+        assert!(runs.contains(&"sudo pacman -U --noconfirm --asdeps /cache/foo/foo-libs-1.0-1-x86_64.pkg.tar.zst".to_string()));
+    }
+
+    #[test]
+    fn dependants_of_a_rejected_base_are_not_built() {
+        crate::utils::prompt::set_input_mode(true, false);
+        let runner = FakeRunner::new()
+            .with_status("pacman -T lib-git cmake", false, "lib-git\ncmake\n")
+            .with_output("pacman -Sp --print-format %n cmake", "cmake\n")
+            .with_output("git rev-parse HEAD", "abc123\n")
+            .with_output("makepkg --packagelist", "/cache/other/other-1.0-1-any.pkg.tar.zst\n");
+        let lookup = lookup(vec![
+            aur("app-git", "app-git", &["lib-git", "cmake"]),
+            aur("lib-git", "lib-git", &[]),
+            aur("other", "other", &[]),
+        ]);
+        let cache = tempfile::tempdir().unwrap();
+        std::fs::write(cache.path().join("reviewed"), "app-git abc123 trusted\nother abc123 trusted\n").unwrap();
+        std::fs::create_dir(cache.path().join("lib-git")).unwrap();
+        std::fs::write(cache.path().join("lib-git/PKGBUILD"), "pkgname=lib-git\n").unwrap();
+
+        let builder = AurBuilder::with_lookup(&runner, &lookup, cache.path()).with_review(true);
+        let report = builder.install(&["app-git", "other"]).unwrap();
+
+        assert_eq!(report.failed, ["app-git"]);
+        assert_eq!(report.installed, ["other"]);
+        let runs = runner.runs();
+        assert!(!runs.iter().any(|r| r.contains("cmake")), "{:?}", runs);
+        assert_eq!(runs.iter().filter(|r| r.starts_with("makepkg")).count(), 1);
+    }
+
+    #[test]
+    fn nothing_is_installed_when_every_base_is_rejected() {
+        crate::utils::prompt::set_input_mode(true, false);
+        let runner = FakeRunner::new()
+            .with_status("pacman -T cmake", false, "cmake\n")
+            .with_output("pacman -Sp --print-format %n cmake", "cmake\n")
+            .with_output("git rev-parse HEAD", "abc123\n");
+        let lookup = lookup(vec![aur("app-git", "app-git", &["cmake"])]);
+        let cache = tempfile::tempdir().unwrap();
+        std::fs::create_dir(cache.path().join("app-git")).unwrap();
+        std::fs::write(cache.path().join("app-git/PKGBUILD"), "pkgname=app-git\n").unwrap();
+
+        let builder = AurBuilder::with_lookup(&runner, &lookup, cache.path()).with_review(true);
+        let report = builder.install(&["app-git"]).unwrap();
+
+        assert_eq!(report.failed, ["app-git"]);
+        assert!(runner.runs().iter().all(|r| r.starts_with("git clone")), "{:?}", runner.runs());
+    }
+}
@@ -1,2 +1,111 @@
+pub mod apt;
+pub mod archive;
 pub mod aur;
-pub mod pacman; 
\ No newline at end of file
+pub mod backend;
+pub mod cargo;
+pub mod dnf;
+pub mod flatpak;
+pub mod makepkg;
+pub mod npm;
+pub mod pacman;
+pub mod pipx;
+pub mod resolve;
+pub mod review;
+pub mod rpc;
+pub mod verify;
+
+use std::collections::{HashMap, HashSet};
+use crate::core::config::Source;
+
+/// Which packages of an install run succeeded and which failed
+#[derive(Debug, Default)]
+pub struct InstallReport {
+    pub installed: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Installation state of a package on this system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageState {
+    Installed,
+    Outdated,
+    Missing,
+}
+
+/// Snapshot of the packages a backend has installed
+#[derive(Debug, Default)]
+pub struct LocalDatabase {
+    installed: HashSet<String>,
+    outdated: HashSet<String>,
+    versions: HashMap<String, String>,
+}
+
+impl LocalDatabase {
+    pub fn new(installed: HashSet<String>, outdated: HashSet<String>) -> Self {
+        Self { installed, outdated, versions: HashMap::new() }
+    }
+
+    /// Adds installed versions, for package managers that report them
+    pub fn with_versions(mut self, versions: HashMap<String, String>) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    pub fn version(&self, package: &str) -> Option<&str> {
+        self.versions.get(package).map(String::as_str)
+    }
+
+    pub fn state(&self, package: &str) -> PackageState {
+        if self.outdated.contains(package) {
+            PackageState::Outdated
+        } else if self.installed.contains(package) {
+            PackageState::Installed
+        } else {
+            PackageState::Missing
+        }
+    }
+}
+
+/// Local databases of every source in use
+#[derive(Debug, Default)]
+pub struct InstalledPackages {
+    databases: HashMap<Source, LocalDatabase>,
+}
+
+impl InstalledPackages {
+    pub fn insert(&mut self, source: Source, database: LocalDatabase) {
+        self.databases.insert(source, database);
+    }
+
+    /// Packages of sources that could not be queried count as missing
+    pub fn state(&self, source: Source, package: &str) -> PackageState {
+        self.databases
+            .get(&source)
+            .map_or(PackageState::Missing, |db| db.state(package))
+    }
+
+    /// Installed version, `None` if unknown or not installed
+    pub fn version(&self, source: Source, package: &str) -> Option<&str> {
+        self.databases.get(&source).and_then(|db| db.version(package))
+    }
+}
+
+/// Parses `name version` lines
+pub(crate) fn parse_version_list(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((fields.next()?.to_string(), fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Parses output with one package name per line, ignoring anything after the name
+pub(crate) fn parse_package_list(output: &str) -> HashSet<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
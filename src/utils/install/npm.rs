@@ -0,0 +1,79 @@
+use anyhow::{Context, Result};
+use crate::utils::install::{
+    backend::{install_each, remove_each, CommandRunner, PackageBackend},
+    InstallReport, LocalDatabase,
+};
+
+/// Installs global npm packages
+pub struct NpmBackend<'a> {
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> NpmBackend<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self { runner }
+    }
+}
+
+impl PackageBackend for NpmBackend<'_> {
+    fn name(&self) -> String {
+        "npm".to_string()
+    }
+
+    fn is_available(&self) -> bool {
+        self.runner.has_command("npm")
+    }
+
+    fn query(&self) -> Result<LocalDatabase> {
+        let output = self.runner.output("npm", &["ls", "--global", "--depth=0", "--json"])?;
+        let tree: serde_json::Value = serde_json::from_str(&output.stdout)
+            .context("Failed to read the list of global npm packages")?;
+
+        let installed = tree
+            .get("dependencies")
+            .and_then(|d| d.as_object())
+            .map(|dependencies| dependencies.keys().cloned().collect())
+            .unwrap_or_default();
+        Ok(LocalDatabase::new(installed, Default::default()))
+    }
+
+    fn install(&self, packages: &[&str]) -> Result<InstallReport> {
+        Ok(install_each(packages, |package| {
+            self.runner
+                .run("npm", &["install", "--global", package])
+                .with_context(|| format!("Failed to install {} (npm)", package))
+        }))
+    }
+
+    fn remove(&self, packages: &[&str]) -> Result<Vec<String>> {
+        Ok(remove_each(packages, |package| {
+            self.runner
+                .run("npm", &["uninstall", "--global", package])
+                .with_context(|| format!("Failed to remove {} (npm)", package))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::{backend::fake::FakeRunner, PackageState};
+
+    #[test]
+    fn query_reads_global_dependencies() {
+        let runner = FakeRunner::new().with_output(
+            "npm ls --global --depth=0 --json",
+            r#"{"dependencies": {"prettier": {"version": "3.3.3"}, "npm": {"version": "10.9.0"}}}"#,
+        );
+        let db = NpmBackend::new(&runner).query().unwrap();
+
+        assert_eq!(db.state("prettier"), PackageState::Installed);
+        assert_eq!(db.state("eslint"), PackageState::Missing);
+    }
+
+    #[test]
+    fn query_fails_on_unreadable_output() {
+        let runner = FakeRunner::new().with_output("npm ls --global --depth=0 --json", "not json");
+        assert!(NpmBackend::new(&runner).query().is_err());
+    }
+}
@@ -1,39 +1,116 @@
-use std::process::{Command, Stdio};
 use anyhow::{Context, Result};
-use crate::utils::spinner::with_spinner;
+use crate::utils::install::{
+    backend::{ask_for_sudo_password, install_batch, remove_each, CommandRunner, PackageBackend},
+    parse_package_list, parse_version_list, InstallReport, LocalDatabase,
+};
 
-pub fn ask_for_sudo_password() -> Result<()> {
-    let status = Command::new("sudo")
-        .arg("-v")
-        .status()
-        .context("Failed to prompt for sudo password")?;
+/// Installs packages from the official repositories
+pub struct PacmanBackend<'a> {
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> PacmanBackend<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self { runner }
+    }
 
-    if !status.success() {
-        anyhow::bail!("Failed to authenticate with sudo");
+    /// Runs a single pacman transaction for all `packages`
+    fn run_pacman_install(&self, packages: &[&str]) -> Result<()> {
+        let mut args = vec!["pacman", "-S", "--needed", "--noconfirm"];
+        args.extend_from_slice(packages);
+        self.runner
+            .run("sudo", &args)
+            .with_context(|| format!("Failed to install {}", packages.join(", ")))
     }
-    Ok(())
 }
 
-pub fn install_package(package: &str) -> Result<()> {
-    with_spinner(&format!("Installing {}...", package), || {
-        let status = Command::new("sudo")
-            .args(["pacman", "-S", "--noconfirm", package])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .context("Failed to run pacman")?;
-
-        if !status.success() {
-            anyhow::bail!("Failed to install {}", package);
+impl PackageBackend for PacmanBackend<'_> {
+    fn name(&self) -> String {
+        "official repositories".to_string()
+    }
+
+    fn is_available(&self) -> bool {
+        self.runner.has_command("pacman")
+    }
+
+    /// Reads installed and outdated packages with `pacman -Q` and `pacman -Qqu`
+    fn query(&self) -> Result<LocalDatabase> {
+        let installed = self.runner.output("pacman", &["-Q"])?;
+        if !installed.success {
+            anyhow::bail!("Failed to query installed packages");
+        }
+
+        // -Qqu exits with 1 when nothing is outdated, so only its output matters
+        let outdated = self.runner.output("pacman", &["-Qqu"])?;
+
+        Ok(LocalDatabase::new(
+            parse_package_list(&installed.stdout),
+            parse_package_list(&outdated.stdout),
+        ).with_versions(parse_version_list(&installed.stdout)))
+    }
+
+    fn install(&self, packages: &[&str]) -> Result<InstallReport> {
+        if packages.is_empty() {
+            return Ok(InstallReport::default());
+        }
+
+        ask_for_sudo_password(self.runner)?;
+        Ok(install_batch(packages, |packages| self.run_pacman_install(packages)))
+    }
+
+    fn remove(&self, packages: &[&str]) -> Result<Vec<String>> {
+        if packages.is_empty() {
+            return Ok(Vec::new());
         }
-        Ok(())
-    })
+
+        ask_for_sudo_password(self.runner)?;
+        Ok(remove_each(packages, |package| {
+            self.runner
+                .run("sudo", &["pacman", "-Rns", "--noconfirm", package])
+                .with_context(|| format!("Failed to remove {}", package))
+        }))
+    }
 }
 
-pub fn install_packages(packages: &[&str]) -> Result<()> {
-    ask_for_sudo_password()?;
-    for package in packages {
-        install_package(package)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::{backend::fake::FakeRunner, PackageState};
+
+    #[test]
+    fn query_reads_installed_and_outdated_packages() {
+        let runner = FakeRunner::new()
+            .with_output("pacman -Q", "neovim 0.10.2-1\nkitty 0.36.4-1\n")
+            .with_status("pacman -Qqu", true, "kitty\n");
+        let db = PacmanBackend::new(&runner).query().unwrap();
+
+        assert_eq!(db.state("neovim"), PackageState::Installed);
+        assert_eq!(db.state("kitty"), PackageState::Outdated);
+        assert_eq!(db.state("firefox"), PackageState::Missing);
+        assert_eq!(db.version("neovim"), Some("0.10.2-1"));
     }
-    Ok(())
-} 
\ No newline at end of file
+
+    #[test]
+    fn nothing_outdated_is_not_an_error() {
+        let runner = FakeRunner::new()
+            .with_output("pacman -Q", "neovim 0.10.2-1\n")
+            .with_status("pacman -Qqu", false, "");
+        let db = PacmanBackend::new(&runner).query().unwrap();
+        assert_eq!(db.state("neovim"), PackageState::Installed);
+    }
+
+    #[test]
+    fn failed_transaction_falls_back_to_single_installs() {
+        let runner = FakeRunner::new().failing_on("typo");
+        let report = PacmanBackend::new(&runner).install(&["neovim", "typo"]).unwrap();
+
+        assert_eq!(report.installed, ["neovim"]);
+        assert_eq!(report.failed, ["typo"]);
+        assert_eq!(runner.runs(), [
+            "sudo -v",
+            "sudo pacman -S --needed --noconfirm neovim typo",
+            "sudo pacman -S --needed --noconfirm neovim",
+            "sudo pacman -S --needed --noconfirm typo",
+        ]);
+    }
+}
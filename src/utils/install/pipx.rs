@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use crate::utils::install::{
+    backend::{install_each, remove_each, CommandRunner, PackageBackend},
+    parse_package_list, InstallReport, LocalDatabase,
+};
+
+/// Installs Python applications into isolated environments with pipx
+pub struct PipxBackend<'a> {
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> PipxBackend<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self { runner }
+    }
+}
+
+impl PackageBackend for PipxBackend<'_> {
+    fn name(&self) -> String {
+        "pipx".to_string()
+    }
+
+    fn is_available(&self) -> bool {
+        self.runner.has_command("pipx")
+    }
+
+    fn query(&self) -> Result<LocalDatabase> {
+        // Prints `name version` per line
+        let output = self.runner.output("pipx", &["list", "--short"])?;
+        if !output.success {
+            anyhow::bail!("Failed to list pipx packages");
+        }
+        Ok(LocalDatabase::new(parse_package_list(&output.stdout), Default::default()))
+    }
+
+    fn install(&self, packages: &[&str]) -> Result<InstallReport> {
+        Ok(install_each(packages, |package| {
+            self.runner
+                .run("pipx", &["install", package])
+                .with_context(|| format!("Failed to install {} (pipx)", package))
+        }))
+    }
+
+    fn remove(&self, packages: &[&str]) -> Result<Vec<String>> {
+        Ok(remove_each(packages, |package| {
+            self.runner
+                .run("pipx", &["uninstall", package])
+                .with_context(|| format!("Failed to remove {} (pipx)", package))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::{backend::fake::FakeRunner, PackageState};
+
+    #[test]
+    fn query_reads_short_listing() {
+        let runner = FakeRunner::new().with_output("pipx list --short", "black 24.10.0\nruff 0.7.1\n");
+        let db = PipxBackend::new(&runner).query().unwrap();
+
+        assert_eq!(db.state("black"), PackageState::Installed);
+        assert_eq!(db.state("poetry"), PackageState::Missing);
+    }
+}
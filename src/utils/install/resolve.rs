@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+use crate::core::{config::{Distro, Package, Source}, version};
+use crate::utils::install::{verify::PackageSource, InstalledPackages, PackageState};
+
+/// A concrete package name handed to a package manager
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub name: String,
+    pub source: Source,
+    /// Why this name was installed instead of the configured one
+    pub reason: Option<String>,
+    /// Name of the configured package this target comes from
+    pub package: String,
+    /// Exact version to install from the package archive instead of the repositories
+    pub pinned: Option<String>,
+}
+
+impl Target {
+    fn new(name: &str, source: Source, reason: Option<String>) -> Self {
+        Self { name: name.to_string(), source, reason, package: String::new(), pinned: None }
+    }
+
+    /// Whether this is pinned to a version that is not installed yet
+    pub fn needs_pinned_install(&self, db: &InstalledPackages) -> bool {
+        self.pinned.as_deref().is_some_and(|pinned| {
+            !db.version(self.source, &self.name).is_some_and(|installed| version::compare(installed, pinned).is_eq())
+        })
+    }
+
+    pub fn label(&self) -> String {
+        match &self.reason {
+            Some(reason) => format!("{} ({})", self.name, reason),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Packages to install and the ones that cannot be installed on this system
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub targets: Vec<Target>,
+    /// Configured names with the reason they were skipped
+    pub unsupported: Vec<(String, String)>,
+}
+
+/// Turns configured packages into the exact names to install on `distro`.
+///
+/// Groups are expanded to their members and providers are named explicitly,
+/// so pacman never has to ask and `--noconfirm` cannot pick a default.
+/// Names that appear more than once are only installed once. Pacman packages
+/// whose `version` names an exact version are pinned to it.
+pub fn resolve_packages(
+    packages: &[Package],
+    distro: Option<Distro>,
+    db: &InstalledPackages,
+    source: &dyn PackageSource,
+) -> Resolution {
+    let mut repo: Option<HashSet<String>> = None;
+    let mut resolution = Resolution::default();
+
+    for package in packages {
+        let Some(package_source) = package.source(distro).filter(|s| s.is_supported_on(distro)) else {
+            let reason = match (package.source(distro), distro) {
+                (Some(package_source), Some(distro)) => format!("{} is not available on {}", package_source, distro),
+                (Some(package_source), None) => format!("{} is not available on this system", package_source),
+                (None, _) => "unknown distribution, no system package manager".to_string(),
+            };
+            resolution.unsupported.push((package.name.clone(), reason));
+            continue;
+        };
+        if package.group && package_source != Source::Pacman {
+            resolution.unsupported.push((package.name.clone(), "package groups need pacman".to_string()));
+            continue;
+        }
+        let name = package.name_for(distro);
+
+        let resolved = if package.group {
+            resolve_group(name, source)
+        } else if let Some(provider) = &package.provider {
+            vec![Target::new(provider, package_source, Some(format!("provides {}", name)))]
+        } else if !package.alternatives.is_empty() {
+            let repo = repo.get_or_insert_with(|| source.repo_packages().unwrap_or_default());
+            vec![resolve_alternatives(package, name, package_source, db, repo, source)]
+        } else {
+            vec![Target::new(name, package_source, None)]
+        };
+
+        // Only pacman can install an older version, from the archive
+        let exact = package.version_req().ok().flatten()
+            .filter(|_| package_source == Source::Pacman && !package.group);
+
+        for mut target in resolved {
+            target.package = package.name.clone();
+            target.pinned = exact.as_ref().and_then(|req| req.exact()).map(str::to_string);
+            let targets = &mut resolution.targets;
+            if !targets.iter().any(|t| t.name == target.name && t.source == target.source) {
+                targets.push(target);
+            }
+        }
+    }
+
+    resolution
+}
+
+fn resolve_group(group: &str, source: &dyn PackageSource) -> Vec<Target> {
+    let mut members = source.group_members(group).unwrap_or_default();
+    if members.is_empty() {
+        // Unknown groups are left to the package verification to report
+        return vec![Target::new(group, Source::Pacman, None)];
+    }
+
+    members.sort();
+    members
+        .iter()
+        .map(|member| Target::new(member, Source::Pacman, Some(format!("group {}", group))))
+        .collect()
+}
+
+/// Keeps an installed candidate if there is one, otherwise picks the first available
+fn resolve_alternatives(
+    package: &Package,
+    name: &str,
+    package_source: Source,
+    db: &InstalledPackages,
+    repo: &HashSet<String>,
+    source: &dyn PackageSource,
+) -> Target {
+    let candidates: Vec<&str> = std::iter::once(name)
+        .chain(package.alternatives.iter().map(String::as_str))
+        .collect();
+
+    let installed = candidates.iter().find(|c| db.state(package_source, c) != PackageState::Missing);
+    let chosen = installed.copied().or_else(|| match package_source {
+        Source::Pacman => candidates.iter().find(|name| repo.contains(**name)).copied(),
+        Source::Aur => {
+            let available = source.aur_packages(&candidates).unwrap_or_default();
+            candidates.iter().find(|name| available.contains(**name)).copied()
+        }
+        // Other package managers cannot be searched, the first candidate is installed
+        _ => None,
+    });
+
+    match chosen {
+        Some(chosen) if chosen != name => {
+            Target::new(chosen, package_source, Some(format!("alternative to {}", name)))
+        }
+        // Nothing is available, keep the configured name so verification reports it
+        _ => Target::new(name, package_source, None),
+    }
+}
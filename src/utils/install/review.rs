@@ -0,0 +1,274 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use crate::utils::{
+    install::backend::CommandRunner,
+    prompt::{input_mode, read_line, InputMode},
+    output::{print_diff, print_section, print_status, print_subsection, print_success, print_warning},
+};
+
+/// A recorded approval of a package base at a given commit
+#[derive(Debug, Clone)]
+struct Approval {
+    base: String,
+    commit: String,
+    trusted: bool,
+}
+
+/// Remembers which PKGBUILD versions were reviewed.
+///
+/// Every approval is kept so the next review can show a diff, approvals
+/// marked as trusted are not asked about again.
+pub struct TrustStore {
+    path: PathBuf,
+    approvals: Vec<Approval>,
+}
+
+impl TrustStore {
+    /// Reads the store from `path`, a missing file is an empty store
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        // One "base commit trusted|once" line per approval
+        let approvals = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                Some(Approval {
+                    base: fields.next()?.to_string(),
+                    commit: fields.next()?.to_string(),
+                    trusted: fields.next() == Some("trusted"),
+                })
+            })
+            .collect();
+        Ok(Self { path, approvals })
+    }
+
+    pub fn is_trusted(&self, base: &str, commit: &str) -> bool {
+        self.approvals.iter().any(|a| a.trusted && a.base == base && a.commit == commit)
+    }
+
+    /// Commit of the most recently approved version of `base`
+    pub fn last_reviewed(&self, base: &str) -> Option<&str> {
+        self.approvals.iter().rev().find(|a| a.base == base).map(|a| a.commit.as_str())
+    }
+
+    pub fn approve(&mut self, base: &str, commit: &str, trusted: bool) -> Result<()> {
+        self.approvals.push(Approval { base: base.to_string(), commit: commit.to_string(), trusted });
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents: String = self.approvals
+            .iter()
+            .map(|a| format!("{} {} {}\n", a.base, a.commit, if a.trusted { "trusted" } else { "once" }))
+            .collect();
+        std::fs::write(&self.path, contents)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// Answer to a review prompt
+enum Decision {
+    Approve,
+    Trust,
+    Reject,
+}
+
+/// Only a person can approve, so `--yes` and missing input count as a refusal
+fn ask(base: &str) -> Decision {
+    print!("\nBuild {}? [y]es, [t]rust this version, [N]o: ", base);
+    if input_mode() != InputMode::Interactive {
+        println!();
+        print_warning("PKGBUILDs can only be approved in a terminal");
+        return Decision::Reject;
+    }
+
+    let Some(input) = read_line() else {
+        return Decision::Reject;
+    };
+    match input.to_lowercase().as_str() {
+        "y" | "yes" => Decision::Approve,
+        "t" | "trust" => Decision::Trust,
+        _ => Decision::Reject,
+    }
+}
+
+/// Files a review covers: the PKGBUILD and any install scripts
+fn reviewed_files(dir: &Path) -> Result<Vec<String>> {
+    let mut files = vec!["PKGBUILD".to_string()];
+    let mut scripts: Vec<String> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".install"))
+        .collect();
+    scripts.sort();
+    files.extend(scripts);
+    Ok(files)
+}
+
+/// Shows the build files of the clone in `dir` and asks whether to build it.
+///
+/// Versions approved before are shown as a diff against the last approval,
+/// trusted versions are approved without asking.
+pub fn review(runner: &dyn CommandRunner, store: &mut TrustStore, base: &str, dir: &Path) -> Result<bool> {
+    let head = runner.output_in(Some(dir), "git", &["rev-parse", "HEAD"])?;
+    if !head.success {
+        anyhow::bail!("Failed to read the current commit of {}", base);
+    }
+    let commit = head.stdout.trim().to_string();
+
+    if store.is_trusted(base, &commit) {
+        print_success(format!("{} is trusted at this version", base));
+        return Ok(true);
+    }
+
+    let previous = store.last_reviewed(base).filter(|c| *c != commit).map(str::to_string);
+    match &previous {
+        Some(previous) => print_section(format!("Changes to {} since the last review ({})", base, short(previous))),
+        None => print_section(format!("Review {} ({})", base, short(&commit))),
+    }
+
+    for file in reviewed_files(dir)? {
+        let contents = std::fs::read_to_string(dir.join(&file))
+            .with_context(|| format!("Failed to read {} of {}", file, base))?;
+        print_subsection(&file);
+
+        match &previous {
+            Some(previous) => {
+                // Files that did not exist at the reviewed commit show up as fully added
+                let old = runner
+                    .output_in(Some(dir), "git", &["show", &format!("{}:{}", previous, file)])
+                    .ok()
+                    .filter(|output| output.success)
+                    .map(|output| output.stdout)
+                    .unwrap_or_default();
+                if old == contents {
+                    print_status("No changes");
+                } else {
+                    print_diff(&old, &contents);
+                }
+            }
+            None => println!("{}", contents.trim_end()),
+        }
+    }
+
+    match ask(base) {
+        Decision::Approve => store.approve(base, &commit, false)?,
+        Decision::Trust => store.approve(base, &commit, true)?,
+        Decision::Reject => {
+            print_warning(format!("{} was not approved", base));
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(10)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::backend::fake::FakeRunner;
+    use crate::utils::prompt::set_input_mode;
+
+    #[test]
+    fn missing_store_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TrustStore::load(dir.path().join("reviewed")).unwrap();
+        assert!(store.last_reviewed("paru").is_none());
+    }
+
+    #[test]
+    fn loads_approvals_and_skips_malformed_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reviewed");
+        std::fs::write(&path, "paru aaa trusted\nbroken\nparu bbb once\nyay ccc\n").unwrap();
+        let store = TrustStore::load(path).unwrap();
+
+        assert!(store.is_trusted("paru", "aaa"));
+        assert!(!store.is_trusted("paru", "bbb"));
+        assert!(!store.is_trusted("yay", "ccc"));
+        assert_eq!(store.last_reviewed("paru"), Some("bbb"));
+        assert_eq!(store.last_reviewed("yay"), Some("ccc"));
+        assert!(store.last_reviewed("broken").is_none());
+    }
+
+    #[test]
+    fn approvals_are_written_one_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache/reviewed");
+        let mut store = TrustStore::load(path.clone()).unwrap();
+        store.approve("paru", "aaa", false).unwrap();
+        store.approve("yay", "bbb", true).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "paru aaa once\nyay bbb trusted\n");
+        let reloaded = TrustStore::load(path).unwrap();
+        assert!(reloaded.is_trusted("yay", "bbb"));
+        assert_eq!(reloaded.last_reviewed("paru"), Some("aaa"));
+    }
+
+    fn clone_with_pkgbuild() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("PKGBUILD"), "pkgname=paru\npkgver=2\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn trusted_versions_are_approved_without_asking() {
+        let clone = clone_with_pkgbuild();
+        let store_dir = tempfile::tempdir().unwrap();
+        let mut store = TrustStore::load(store_dir.path().join("reviewed")).unwrap();
+        store.approve("paru", "bbb", true).unwrap();
+        let runner = FakeRunner::new().with_output("git rev-parse HEAD", "bbb\n");
+
+        assert!(review(&runner, &mut store, "paru", clone.path()).unwrap());
+    }
+
+    #[test]
+    fn changed_versions_are_diffed_against_the_last_approval() {
+        set_input_mode(true, false);
+        let clone = clone_with_pkgbuild();
+        let store_dir = tempfile::tempdir().unwrap();
+        let mut store = TrustStore::load(store_dir.path().join("reviewed")).unwrap();
+        store.approve("paru", "aaa", false).unwrap();
+        let runner = FakeRunner::new()
+            .with_output("git rev-parse HEAD", "bbb\n")
+            .with_output("git show aaa:PKGBUILD", "pkgname=paru\npkgver=1\n");
+
+        review(&runner, &mut store, "paru", clone.path()).unwrap();
+        assert!(runner.commands.borrow().contains(&"output: git show aaa:PKGBUILD".to_string()));
+    }
+
+    #[test]
+    fn first_reviews_show_the_files_without_a_diff() {
+        set_input_mode(true, false);
+        let clone = clone_with_pkgbuild();
+        let store_dir = tempfile::tempdir().unwrap();
+        let mut store = TrustStore::load(store_dir.path().join("reviewed")).unwrap();
+        let runner = FakeRunner::new().with_output("git rev-parse HEAD", "bbb\n");
+
+        review(&runner, &mut store, "paru", clone.path()).unwrap();
+        assert!(!runner.commands.borrow().iter().any(|c| c.contains("git show")));
+    }
+
+    #[test]
+    fn assume_yes_never_approves() {
+        set_input_mode(true, false);
+        let clone = clone_with_pkgbuild();
+        let store_dir = tempfile::tempdir().unwrap();
+        let path = store_dir.path().join("reviewed");
+        let mut store = TrustStore::load(path.clone()).unwrap();
+        store.approve("paru", "aaa", false).unwrap();
+        let runner = FakeRunner::new().with_output("git rev-parse HEAD", "bbb\n");
+
+        assert!(!review(&runner, &mut store, "paru", clone.path()).unwrap());
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "paru aaa once\n");
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+const AUR_RPC_URL: &str = "https://aur.archlinux.org/rpc/v5";
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A package as returned by the AUR RPC interface
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AurPackage {
+    pub name: String,
+    pub package_base: String,
+    pub version: String,
+    #[serde(default)]
+    pub depends: Vec<String>,
+    #[serde(default)]
+    pub make_depends: Vec<String>,
+    #[serde(default)]
+    pub check_depends: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    results: Vec<AurPackage>,
+}
+
+fn request(url: &str, query: &[(&str, &str)]) -> Result<Vec<AurPackage>> {
+    let response: Response = ureq::get(url)
+        .timeout(TIMEOUT)
+        .query_pairs(query.iter().copied())
+        .call()
+        .context("Failed to reach the AUR")?
+        .into_json()
+        .context("Invalid response from the AUR")?;
+
+    if response.kind == "error" {
+        anyhow::bail!("AUR request failed: {}", response.error.unwrap_or_default());
+    }
+    Ok(response.results)
+}
+
+/// Looks up the given packages, names that do not exist are left out of the result
+pub fn info(names: &[&str]) -> Result<Vec<AurPackage>> {
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let query: Vec<_> = names.iter().map(|name| ("arg[]", *name)).collect();
+    request(&format!("{}/info", AUR_RPC_URL), &query)
+}
+
+/// Searches package names containing `query`
+pub fn search(query: &str) -> Result<Vec<AurPackage>> {
+    request(&format!("{}/search/{}", AUR_RPC_URL, encode_segment(query)), &[("by", "name")])
+}
+
+/// Percent-encodes everything but unreserved characters so `value` stays one path segment
+fn encode_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_reserved_characters_in_search_terms() {
+        assert_eq!(encode_segment("python-foo_bar.1~"), "python-foo_bar.1~");
+        assert_eq!(encode_segment("gtk+"), "gtk%2B");
+        assert_eq!(encode_segment("a/b?c#d e"), "a%2Fb%3Fc%23d%20e");
+        assert_eq!(encode_segment("ä"), "%C3%A4");
+    }
+}
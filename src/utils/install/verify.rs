@@ -0,0 +1,300 @@
+use std::collections::HashSet;
+use std::process::Command;
+use anyhow::{Context, Result};
+use crate::core::config::Source;
+use crate::utils::install::{resolve::Target, rpc};
+
+/// Number of close matches suggested for an unknown package
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Where package names are looked up, so verification can run against a fixed list
+pub trait PackageSource {
+    /// Every package and group name in the sync databases
+    fn repo_packages(&self) -> Result<HashSet<String>>;
+    /// The subset of `names` that exists in the AUR
+    fn aur_packages(&self, names: &[&str]) -> Result<HashSet<String>>;
+    /// AUR package names containing `query`
+    fn aur_search(&self, query: &str) -> Result<Vec<String>>;
+    /// Members of a package group in the sync databases
+    fn group_members(&self, group: &str) -> Result<Vec<String>>;
+}
+
+/// Looks packages up with pacman and the AUR RPC interface
+pub struct SystemPackageSource;
+
+impl PackageSource for SystemPackageSource {
+    fn repo_packages(&self) -> Result<HashSet<String>> {
+        let packages = pacman_lines(&["-Slq"])?;
+        // Group listing prints "group package" pairs
+        let groups = pacman_lines(&["-Sg"])?;
+
+        Ok(packages
+            .into_iter()
+            .chain(groups.into_iter().filter_map(|line| line.split_whitespace().next().map(str::to_string)))
+            .collect())
+    }
+
+    fn aur_packages(&self, names: &[&str]) -> Result<HashSet<String>> {
+        Ok(rpc::info(names)?.into_iter().map(|p| p.name).collect())
+    }
+
+    fn aur_search(&self, query: &str) -> Result<Vec<String>> {
+        Ok(rpc::search(query)?.into_iter().map(|p| p.name).collect())
+    }
+
+    fn group_members(&self, group: &str) -> Result<Vec<String>> {
+        pacman_lines(&["-Sgq", group])
+    }
+}
+
+fn pacman_lines(args: &[&str]) -> Result<Vec<String>> {
+    let output = Command::new("pacman")
+        .args(args)
+        .output()
+        .context("Failed to run pacman")?;
+
+    if !output.status.success() {
+        anyhow::bail!("pacman {} failed", args.join(" "));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+}
+
+/// A problem found with a configured package
+#[derive(Debug)]
+pub enum Issue {
+    /// The package exists nowhere, with similarly named packages
+    NotFound { suggestions: Vec<String> },
+    /// Configured for pacman but only available in the AUR
+    OnlyInAur,
+    /// Configured for the AUR but available in the official repositories
+    InRepos,
+}
+
+#[derive(Debug)]
+pub struct PackageIssue {
+    pub package: String,
+    pub issue: Issue,
+}
+
+impl PackageIssue {
+    /// Packages that would fail to install, as opposed to ones that are just misconfigured
+    pub fn is_error(&self) -> bool {
+        !matches!(self.issue, Issue::InRepos)
+    }
+
+    pub fn message(&self) -> String {
+        match &self.issue {
+            Issue::NotFound { suggestions } if suggestions.is_empty() => {
+                format!("{}: package not found", self.package)
+            }
+            Issue::NotFound { suggestions } => format!(
+                "{}: package not found, did you mean {}?",
+                self.package,
+                suggestions.join(", ")
+            ),
+            Issue::OnlyInAur => format!("{}: only available in the AUR, set `source: aur`", self.package),
+            Issue::InRepos => format!(
+                "{}: available in the official repositories, it does not need the AUR",
+                self.package
+            ),
+        }
+    }
+}
+
+/// Outcome of verifying all configured packages
+#[derive(Debug, Default)]
+pub struct Verification {
+    pub issues: Vec<PackageIssue>,
+    /// Lookups that could not be done, e.g. because the AUR was unreachable
+    pub skipped: Vec<String>,
+}
+
+impl Verification {
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(PackageIssue::is_error)
+    }
+}
+
+/// Checks that every resolved package exists where the configuration says it does
+pub fn verify_packages(packages: &[&Target], source: &dyn PackageSource) -> Verification {
+    let mut verification = Verification::default();
+
+    // Only pacman and the AUR can be searched
+    let packages: Vec<&Target> = packages
+        .iter()
+        .copied()
+        .filter(|p| matches!(p.source, Source::Pacman | Source::Aur))
+        .collect();
+    if packages.is_empty() {
+        return verification;
+    }
+
+    let repo = match source.repo_packages() {
+        Ok(repo) => Some(repo),
+        Err(e) => {
+            verification.skipped.push(format!("Could not read the sync databases: {:#}", e));
+            None
+        }
+    };
+
+    // Only ask the AUR about packages the official repositories do not answer for
+    let aur_candidates: Vec<&str> = packages
+        .iter()
+        .filter(|p| p.source == Source::Aur || repo.as_ref().is_some_and(|r| !r.contains(&p.name)))
+        .map(|p| p.name.as_str())
+        .collect();
+    let aur = match source.aur_packages(&aur_candidates) {
+        Ok(aur) => Some(aur),
+        Err(e) => {
+            verification.skipped.push(format!("Could not reach the AUR, skipping AUR checks ({})", e));
+            None
+        }
+    };
+
+    for package in &packages {
+        let in_repo = repo.as_ref().map(|r| r.contains(&package.name));
+        let in_aur = aur.as_ref().map(|a| a.contains(&package.name));
+
+        let issue = match (package.source == Source::Aur, in_repo, in_aur) {
+            (false, Some(false), Some(true)) => Some(Issue::OnlyInAur),
+            (false, Some(false), _) | (true, Some(false) | None, Some(false)) => Some(Issue::NotFound {
+                suggestions: suggestions(&package.name, repo.as_ref(), aur.is_some(), source),
+            }),
+            (true, Some(true), _) => Some(Issue::InRepos),
+            _ => None,
+        };
+
+        if let Some(issue) = issue {
+            verification.issues.push(PackageIssue { package: package.name.clone(), issue });
+        }
+    }
+
+    verification
+}
+
+/// Collects the closest known names to `name` from the repositories and the AUR
+fn suggestions(
+    name: &str,
+    repo: Option<&HashSet<String>>,
+    search_aur: bool,
+    source: &dyn PackageSource,
+) -> Vec<String> {
+    // The AUR only searches by substring, so look for names sharing the first half
+    let prefix: String = name.chars().take((name.chars().count() / 2).max(2)).collect();
+    let aur = if search_aur { source.aur_search(&prefix).unwrap_or_default() } else { Vec::new() };
+
+    let mut scored: Vec<(f64, &str)> = repo
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .chain(aur.iter().map(String::as_str))
+        .map(|candidate| (strsim::jaro_winkler(name, candidate), candidate))
+        .filter(|(score, _)| *score > 0.8)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+
+    let mut result: Vec<String> = Vec::new();
+    for (_, candidate) in scored {
+        if !result.iter().any(|r| r == candidate) {
+            result.push(candidate.to_string());
+        }
+        if result.len() == MAX_SUGGESTIONS {
+            break;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Package source answering from fixed lists, with the AUR optionally unreachable
+    struct FakeSource {
+        repo: Vec<&'static str>,
+        aur: Option<Vec<&'static str>>,
+    }
+
+    impl PackageSource for FakeSource {
+        fn repo_packages(&self) -> Result<HashSet<String>> {
+            Ok(self.repo.iter().map(|p| p.to_string()).collect())
+        }
+
+        fn aur_packages(&self, names: &[&str]) -> Result<HashSet<String>> {
+            let aur = self.aur.as_ref().ok_or_else(|| anyhow::anyhow!("connection refused"))?;
+            Ok(names.iter().filter(|n| aur.contains(n)).map(|n| n.to_string()).collect())
+        }
+
+        fn aur_search(&self, query: &str) -> Result<Vec<String>> {
+            let aur = self.aur.as_ref().ok_or_else(|| anyhow::anyhow!("connection refused"))?;
+            Ok(aur.iter().filter(|p| p.contains(query)).map(|p| p.to_string()).collect())
+        }
+
+        fn group_members(&self, _group: &str) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn target(name: &str, source: Source) -> Target {
+        Target { name: name.to_string(), source, reason: None, package: name.to_string(), pinned: None }
+    }
+
+    fn source() -> FakeSource {
+        FakeSource {
+            repo: vec!["neovim", "firefox", "kitty"],
+            aur: Some(vec!["paru-bin", "neovim-git"]),
+        }
+    }
+
+    fn verify(source: &FakeSource, targets: &[Target]) -> Verification {
+        let targets: Vec<&Target> = targets.iter().collect();
+        verify_packages(&targets, source)
+    }
+
+    #[test]
+    fn known_packages_have_no_issues() {
+        let verification = verify(&source(), &[target("neovim", Source::Pacman), target("paru-bin", Source::Aur)]);
+        assert!(verification.issues.is_empty());
+        assert!(verification.skipped.is_empty());
+    }
+
+    #[test]
+    fn typo_is_not_found_with_suggestions() {
+        let verification = verify(&source(), &[target("neovimm", Source::Pacman)]);
+        assert_eq!(verification.issues.len(), 1);
+        match &verification.issues[0].issue {
+            Issue::NotFound { suggestions } => assert_eq!(suggestions[0], "neovim"),
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+        assert!(verification.has_errors());
+    }
+
+    #[test]
+    fn aur_package_configured_for_pacman() {
+        let verification = verify(&source(), &[target("paru-bin", Source::Pacman)]);
+        assert!(matches!(verification.issues[0].issue, Issue::OnlyInAur));
+        assert!(verification.has_errors());
+    }
+
+    #[test]
+    fn repo_package_configured_for_aur() {
+        let verification = verify(&source(), &[target("kitty", Source::Aur)]);
+        assert!(matches!(verification.issues[0].issue, Issue::InRepos));
+        assert!(!verification.has_errors());
+    }
+
+    #[test]
+    fn unreachable_aur_skips_aur_lookups() {
+        let offline = FakeSource { aur: None, ..source() };
+        let verification = verify(&offline, &[target("paru-bin", Source::Aur), target("firefox", Source::Pacman)]);
+        assert!(verification.issues.is_empty());
+        assert_eq!(verification.skipped.len(), 1);
+        assert!(verification.skipped[0].contains("Could not reach the AUR"));
+    }
+
+    #[test]
+    fn other_sources_are_not_verified() {
+        let verification = verify(&source(), &[target("ripgrep", Source::Cargo)]);
+        assert!(verification.issues.is_empty());
+    }
+}
@@ -4,4 +4,17 @@ pub mod spinner;
 pub mod system;
 pub mod install;
 pub mod files;
-pub mod backup;
\ No newline at end of file
+pub mod backup;
+pub mod prompt;
+pub mod permissions;
+pub mod state;
+pub mod status;
+pub mod select;
+pub mod hooks;
+pub mod services;
+pub mod system_settings;
+pub mod encryption;
+pub mod secrets;
+pub mod render;
+pub mod copy;
+pub mod process;
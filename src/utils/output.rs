@@ -1,9 +1,11 @@
 use colored::*;
+use similar::{ChangeTag, TextDiff};
 use std::fmt::Display;
 
 const PREFIX_STEP: &str = "→";
 const PREFIX_SUCCESS: &str = "✓";
 const PREFIX_ERROR: &str = "✗";
+const PREFIX_WARNING: &str = "!";
 const PREFIX_INFO: &str = "•";
 const INDENT: &str = "  ";
 
@@ -19,6 +21,10 @@ pub fn print_error<T: Display>(message: T) {
     println!("{} {}", PREFIX_ERROR.bright_red(), message);
 }
 
+pub fn print_warning<T: Display>(message: T) {
+    println!("{} {}", PREFIX_WARNING.bright_yellow(), message);
+}
+
 pub fn print_status<T: Display>(message: T) {
     println!("{}", message);
 }
@@ -41,4 +47,25 @@ pub fn print_subsection<T: Display>(title: T) {
 
 pub fn print_separator() {
     println!("\n{}", "─".repeat(50).bright_black());
-} 
\ No newline at end of file
+}
+
+/// Prints a colored line diff between two versions of a file
+pub fn print_diff(old: &str, new: &str) {
+    let diff = TextDiff::from_lines(old, new);
+    for (i, group) in diff.grouped_ops(3).iter().enumerate() {
+        if i > 0 {
+            println!("{}", "...".bright_black());
+        }
+        for op in group {
+            for change in diff.iter_changes(op) {
+                let line = change.to_string_lossy();
+                let line = line.trim_end_matches('\n');
+                match change.tag() {
+                    ChangeTag::Delete => println!("{}", format!("-{}", line).red()),
+                    ChangeTag::Insert => println!("{}", format!("+{}", line).green()),
+                    ChangeTag::Equal => println!(" {}", line),
+                }
+            }
+        }
+    }
+}
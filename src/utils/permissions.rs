@@ -0,0 +1,204 @@
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use anyhow::{Context, Result};
+use nix::unistd::{Group, User};
+use walkdir::WalkDir;
+use crate::core::config::FileMapping;
+
+/// Permissions and ownership a deployed path should have
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Attributes {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// Resolved attributes for the files and directories of a mapping
+pub struct MappingAttributes {
+    pub file: Attributes,
+    pub dir: Attributes,
+}
+
+impl MappingAttributes {
+    pub fn from_mapping(mapping: &FileMapping) -> Result<Self> {
+        let uid = match &mapping.owner {
+            Some(name) => Some(
+                User::from_name(name)?
+                    .ok_or_else(|| anyhow::anyhow!("Unknown user: {}", name))?
+                    .uid
+                    .as_raw(),
+            ),
+            None => None,
+        };
+        let gid = match &mapping.group {
+            Some(name) => Some(
+                Group::from_name(name)?
+                    .ok_or_else(|| anyhow::anyhow!("Unknown group: {}", name))?
+                    .gid
+                    .as_raw(),
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            file: Attributes { mode: mapping.mode.map(|m| m.0), uid, gid },
+            dir: Attributes { mode: mapping.dir_mode.map(|m| m.0), uid, gid },
+        })
+    }
+
+    fn for_entry(&self, is_dir: bool) -> &Attributes {
+        if is_dir { &self.dir } else { &self.file }
+    }
+}
+
+/// Lists the deployed paths that correspond to entries of `source`.
+///
+/// Only paths that come from the repository are returned, other files in a
+/// destination directory are left alone. Symlinks are skipped since their
+/// permissions cannot be changed.
+pub fn deployed_entries(source: &Path, destination: &Path) -> Vec<(PathBuf, bool)> {
+    if !source.is_dir() {
+        return vec![(destination.to_path_buf(), false)];
+    }
+
+    WalkDir::new(source)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.path_is_symlink())
+        .filter_map(|e| {
+            let relative = e.path().strip_prefix(source).ok()?;
+            Some((destination.join(relative), e.file_type().is_dir()))
+        })
+        .collect()
+}
+
+/// Describes how `path` differs from the expected attributes
+pub fn drift(path: &Path, expected: &Attributes) -> Result<Vec<String>> {
+    let metadata = std::fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut issues = Vec::new();
+
+    if let Some(mode) = expected.mode {
+        let actual = metadata.permissions().mode() & 0o7777;
+        if actual != mode {
+            issues.push(format!("mode is {:04o}, expected {:04o}", actual, mode));
+        }
+    }
+    if let Some(uid) = expected.uid {
+        if metadata.uid() != uid {
+            issues.push(format!("owner is {}, expected {}", user_name(metadata.uid()), user_name(uid)));
+        }
+    }
+    if let Some(gid) = expected.gid {
+        if metadata.gid() != gid {
+            issues.push(format!("group is {}, expected {}", group_name(metadata.gid()), group_name(gid)));
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Collects attribute drift for every deployed path of a mapping
+pub fn mapping_drift(source: &Path, destination: &Path, mapping: &FileMapping) -> Result<Vec<String>> {
+    if !mapping.has_attributes() {
+        return Ok(Vec::new());
+    }
+
+    let attributes = MappingAttributes::from_mapping(mapping)?;
+    let mut issues = Vec::new();
+
+    for (path, is_dir) in deployed_entries(source, destination) {
+        if !path.exists() {
+            continue;
+        }
+        for issue in drift(&path, attributes.for_entry(is_dir))? {
+            issues.push(format!("{}: {}", path.display(), issue));
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Applies the mapping's permissions and ownership to everything it deployed.
+///
+/// Falls back to sudo when the current user is not allowed to make a change.
+pub fn apply_mapping(source: &Path, destination: &Path, mapping: &FileMapping) -> Result<()> {
+    if !mapping.has_attributes() {
+        return Ok(());
+    }
+
+    let attributes = MappingAttributes::from_mapping(mapping)?;
+    for (path, is_dir) in deployed_entries(source, destination) {
+        if path.exists() {
+            apply(&path, attributes.for_entry(is_dir))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies attributes to a single path, leaving already correct values untouched
+pub fn apply(path: &Path, expected: &Attributes) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    // Change the mode first, once ownership moves to another user we may no longer be allowed to
+    if let Some(mode) = expected.mode {
+        if metadata.permissions().mode() & 0o7777 != mode {
+            let result = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode));
+            if let Err(e) = result {
+                if e.kind() != std::io::ErrorKind::PermissionDenied {
+                    return Err(e).with_context(|| format!("Failed to set mode of {}", path.display()));
+                }
+                sudo(&["chmod", &format!("{:o}", mode)], path)?;
+            }
+        }
+    }
+
+    let uid = expected.uid.filter(|uid| *uid != metadata.uid());
+    let gid = expected.gid.filter(|gid| *gid != metadata.gid());
+    if uid.is_some() || gid.is_some() {
+        if let Err(e) = std::os::unix::fs::chown(path, uid, gid) {
+            if e.kind() != std::io::ErrorKind::PermissionDenied {
+                return Err(e).with_context(|| format!("Failed to change owner of {}", path.display()));
+            }
+            let owner = format!(
+                "{}:{}",
+                uid.map(|u| u.to_string()).unwrap_or_default(),
+                gid.map(|g| g.to_string()).unwrap_or_default()
+            );
+            sudo(&["chown", &owner], path)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn sudo(args: &[&str], path: &Path) -> Result<()> {
+    let status = Command::new("sudo")
+        .args(args)
+        .arg(path)
+        .stdout(Stdio::null())
+        .status()
+        .context("Failed to run sudo")?;
+
+    if !status.success() {
+        anyhow::bail!("Failed to run {} on {}", args[0], path.display());
+    }
+    Ok(())
+}
+
+fn user_name(uid: u32) -> String {
+    User::from_uid(uid.into())
+        .ok()
+        .flatten()
+        .map_or_else(|| uid.to_string(), |u| u.name)
+}
+
+fn group_name(gid: u32) -> String {
+    Group::from_gid(gid.into())
+        .ok()
+        .flatten()
+        .map_or_else(|| gid.to_string(), |g| g.name)
+}
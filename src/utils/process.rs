@@ -0,0 +1,175 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use anyhow::{Context, Result};
+use colored::*;
+use crate::utils::output::print_status;
+
+/// Number of stderr lines kept for error reports
+const TAIL_LINES: usize = 15;
+
+struct RunLog {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+static VERBOSE: OnceLock<bool> = OnceLock::new();
+static LOG: Mutex<Option<RunLog>> = Mutex::new(None);
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Shown instead of secret values
+pub const MASK: &str = "********";
+
+/// Keeps `value` out of the log and the terminal from now on
+pub fn add_secret(value: &str) {
+    let mut secrets = SECRETS.lock().unwrap();
+    if !value.is_empty() && !secrets.iter().any(|s| s == value) {
+        secrets.push(value.to_string());
+        // Longer values first, so a secret containing another is masked whole
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    }
+}
+
+/// Replaces every known secret value in `text`
+pub fn mask(text: &str) -> String {
+    SECRETS.lock().unwrap().iter().fold(text.to_string(), |text, secret| text.replace(secret.as_str(), MASK))
+}
+
+/// Streams the output of external commands to the terminal as it is produced
+pub fn set_verbose(verbose: bool) {
+    let _ = VERBOSE.set(verbose);
+}
+
+fn is_verbose() -> bool {
+    VERBOSE.get().copied().unwrap_or(false)
+}
+
+/// Path of this run's log file, it is only created once a command runs
+pub fn log_path() -> PathBuf {
+    LOG.lock()
+        .unwrap()
+        .get_or_insert_with(|| RunLog { path: new_log_path(), file: None })
+        .path
+        .clone()
+}
+
+fn new_log_path() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("dotty")
+        .join("logs")
+        .join(format!("install-{}.log", chrono::Local::now().format("%Y%m%d-%H%M%S")))
+}
+
+fn write_log(line: &str) {
+    let line = mask(line);
+    let mut log = LOG.lock().unwrap();
+    let log = log.get_or_insert_with(|| RunLog { path: new_log_path(), file: None });
+
+    if log.file.is_none() {
+        if let Some(parent) = log.path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        log.file = File::options().create(true).append(true).open(&log.path).ok();
+    }
+    if let Some(file) = &mut log.file {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// A command that exited unsuccessfully, with the end of what it wrote to stderr
+#[derive(Debug)]
+pub struct CommandError {
+    pub command: String,
+    pub code: Option<i32>,
+    pub stderr_tail: Vec<String>,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{} exited with status {}", self.command, code),
+            None => write!(f, "{} was terminated by a signal", self.command),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+fn describe(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Forwards every line of `reader` to the log and, in verbose mode, the terminal
+fn forward<R: Read + Send + 'static>(reader: R, keep_tail: bool) -> thread::JoinHandle<Vec<String>> {
+    thread::spawn(move || {
+        let mut tail = Vec::new();
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            let line = mask(&line);
+            write_log(&line);
+            if is_verbose() {
+                // Clear the spinner line, it redraws itself below the output
+                print!("\r\x1b[K");
+                println!("    {}", line.bright_black());
+            }
+            if keep_tail {
+                tail.push(line);
+                if tail.len() > TAIL_LINES {
+                    tail.remove(0);
+                }
+            }
+        }
+        tail
+    })
+}
+
+/// Runs `command` with its output captured into the run log.
+///
+/// Stdin stays attached to the terminal so sudo can still ask for a password.
+/// A non-zero exit status is returned as a [`CommandError`].
+pub fn run(command: &mut Command) -> Result<()> {
+    let description = mask(&describe(command));
+    write_log(&format!("$ {}", description));
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", description))?;
+
+    let stdout = forward(child.stdout.take().expect("stdout is piped"), false);
+    let stderr = forward(child.stderr.take().expect("stderr is piped"), true);
+
+    let status = child.wait().with_context(|| format!("Failed to wait for {}", description))?;
+    let _ = stdout.join();
+    let stderr_tail = stderr.join().unwrap_or_default();
+
+    write_log(&format!("[exit: {}]", status));
+    if !status.success() {
+        return Err(CommandError { command: description, code: status.code(), stderr_tail }.into());
+    }
+    Ok(())
+}
+
+/// Prints the stderr tail of a failed command and where to find the full log
+pub fn print_failure_details(error: &anyhow::Error) {
+    let Some(failure) = error.chain().find_map(|e| e.downcast_ref::<CommandError>()) else {
+        return;
+    };
+
+    // Output was already shown live in verbose mode
+    if !is_verbose() {
+        for line in &failure.stderr_tail {
+            print_status(format!("    {}", line.bright_black()));
+        }
+    }
+    print_status(format!("    Full output: {}", log_path().display()).bright_black());
+}
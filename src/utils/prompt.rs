@@ -0,0 +1,76 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+use crate::utils::output::{print_error, print_status};
+
+/// How questions are answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// Ask on stdin
+    Interactive,
+    /// Answer yes to every confirmation without asking (`--yes`)
+    AssumeYes,
+    /// Never read stdin (`--no-input`, or stdin is not a terminal)
+    NoInput,
+}
+
+static MODE: OnceLock<InputMode> = OnceLock::new();
+
+/// Picks the input mode for this run, stdin that is not a terminal counts as `--no-input`
+pub fn set_input_mode(yes: bool, no_input: bool) {
+    let mode = if yes {
+        InputMode::AssumeYes
+    } else if no_input || !io::stdin().is_terminal() {
+        InputMode::NoInput
+    } else {
+        InputMode::Interactive
+    };
+    let _ = MODE.set(mode);
+}
+
+pub fn input_mode() -> InputMode {
+    MODE.get().copied().unwrap_or(InputMode::Interactive)
+}
+
+/// Reads one line from stdin, `None` at the end of input or without input
+pub fn read_line() -> Option<String> {
+    if input_mode() != InputMode::Interactive {
+        return None;
+    }
+    io::stdout().flush().ok();
+
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input.trim().to_string()),
+    }
+}
+
+/// Asks a yes/no question on stdin, an empty answer counts as yes.
+///
+/// With `--yes` the question is answered without asking. Without input it
+/// cannot be answered at all, so dotty exits instead of guessing.
+pub fn confirm(question: &str) -> bool {
+    print!("{} [Y/n] ", question);
+    match input_mode() {
+        InputMode::AssumeYes => {
+            println!("yes (--yes)");
+            println!();
+            return true;
+        }
+        InputMode::NoInput => {
+            println!();
+            print_error("No input available to answer this question");
+            print_status("Pass --yes to accept, or run dotty in a terminal");
+            std::process::exit(1);
+        }
+        InputMode::Interactive => {}
+    }
+
+    // The end of input is never taken as a yes
+    let answer = read_line();
+    println!();
+    match answer {
+        Some(input) => !matches!(input.to_lowercase().as_str(), "n" | "no"),
+        None => false,
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::core::config::{DottyConfig, FileMapping};
+use crate::utils::encryption::Decryptor;
+use crate::utils::secrets::Secrets;
+
+/// Produces what a mapping deploys, decrypting and filling in templates as needed
+pub struct SourceReader<'a> {
+    decryptor: Decryptor,
+    secrets: Secrets<'a>,
+}
+
+impl<'a> SourceReader<'a> {
+    pub fn new(config: &'a DottyConfig) -> Self {
+        Self {
+            decryptor: Decryptor::new(config.identity_file()),
+            secrets: Secrets::new(&config.secrets),
+        }
+    }
+
+    pub fn decryptor(&self) -> &Decryptor {
+        &self.decryptor
+    }
+
+    /// Contents deployed for the single file `source` of `mapping`
+    pub fn read(&self, mapping: &FileMapping, source: &Path) -> Result<Vec<u8>> {
+        let contents = if mapping.encrypted {
+            self.decryptor.decrypt(source)?
+        } else {
+            std::fs::read(source).with_context(|| format!("Failed to read {}", source.display()))?
+        };
+        if !mapping.template {
+            return Ok(contents);
+        }
+
+        let template = String::from_utf8(contents)
+            .map_err(|_| anyhow::anyhow!("Template {} is not valid UTF-8", source.display()))?;
+        Ok(self.secrets.render(&template)?.into_bytes())
+    }
+}
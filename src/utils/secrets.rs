@@ -0,0 +1,162 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::process::{Command, Stdio};
+use anyhow::{Context, Result};
+use crate::core::config::SecretSource;
+use crate::utils::process;
+
+/// Reads the value of a secret from where it is kept
+pub trait SecretProvider {
+    /// Name shown in errors
+    fn name(&self) -> String;
+    fn fetch(&self) -> Result<String>;
+}
+
+pub struct EnvProvider(pub String);
+pub struct FileProvider(pub String);
+pub struct PassProvider(pub String);
+pub struct CommandProvider(pub String);
+
+impl SecretProvider for EnvProvider {
+    fn name(&self) -> String {
+        format!("environment variable {}", self.0)
+    }
+
+    fn fetch(&self) -> Result<String> {
+        std::env::var(&self.0).with_context(|| format!("{} is not set", self.0))
+    }
+}
+
+impl SecretProvider for FileProvider {
+    fn name(&self) -> String {
+        format!("file {}", self.0)
+    }
+
+    fn fetch(&self) -> Result<String> {
+        let path = shellexpand::tilde(&self.0).into_owned();
+        let contents = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path))?;
+        Ok(contents.trim_end_matches(['\n', '\r']).to_string())
+    }
+}
+
+/// Runs `command` and returns what it printed, without the trailing newline.
+///
+/// Stdin and stderr stay on the terminal so gpg and password managers can ask
+/// for a passphrase. The output never reaches the run log.
+fn captured(command: &mut Command) -> Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!("{} exited with {}", program, output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end_matches(['\n', '\r']).to_string())
+}
+
+impl SecretProvider for PassProvider {
+    fn name(&self) -> String {
+        format!("pass entry {}", self.0)
+    }
+
+    /// Only the first line, the rest of a pass entry is metadata by convention
+    fn fetch(&self) -> Result<String> {
+        let entry = captured(Command::new("pass").args(["show", &self.0]))?;
+        Ok(entry.lines().next().unwrap_or_default().to_string())
+    }
+}
+
+impl SecretProvider for CommandProvider {
+    fn name(&self) -> String {
+        format!("command `{}`", self.0)
+    }
+
+    fn fetch(&self) -> Result<String> {
+        captured(Command::new("sh").args(["-c", &self.0]))
+    }
+}
+
+pub fn provider_for(source: &SecretSource) -> Box<dyn SecretProvider> {
+    match source {
+        SecretSource::Env { env } => Box::new(EnvProvider(env.clone())),
+        SecretSource::File { file } => Box::new(FileProvider(file.clone())),
+        SecretSource::Pass { pass } => Box::new(PassProvider(pass.clone())),
+        SecretSource::Command { command } => Box::new(CommandProvider(command.clone())),
+    }
+}
+
+/// Names of the secrets a template refers to with `{{ secrets.NAME }}`, with the
+/// byte range of each reference
+fn references(template: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find("{{").map(|i| offset + i) {
+        let Some(end) = template[start..].find("}}").map(|i| start + i + 2) else {
+            break;
+        };
+        // Other `{{ }}` expressions belong to the file itself and are left alone
+        match template[start + 2..end - 2].trim().strip_prefix("secrets.") {
+            Some(name) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                found.push((start..end, name));
+                offset = end;
+            }
+            _ => offset = start + 2,
+        }
+    }
+    found
+}
+
+/// Names of the secrets used by `template`
+pub fn referenced_secrets(template: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = references(template).into_iter().map(|(_, name)| name).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The secrets of a configuration, each fetched once when first needed
+pub struct Secrets<'a> {
+    sources: &'a BTreeMap<String, SecretSource>,
+    values: RefCell<HashMap<String, String>>,
+}
+
+impl<'a> Secrets<'a> {
+    pub fn new(sources: &'a BTreeMap<String, SecretSource>) -> Self {
+        Self { sources, values: RefCell::new(HashMap::new()) }
+    }
+
+    /// Value of the secret `name`, masked in all output from then on
+    pub fn get(&self, name: &str) -> Result<String> {
+        if let Some(value) = self.values.borrow().get(name) {
+            return Ok(value.clone());
+        }
+
+        let source = self.sources
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Secret `{}` is not defined in `secrets`", name))?;
+        let provider = provider_for(source);
+        let value = provider
+            .fetch()
+            .with_context(|| format!("Failed to read secret `{}` from {}", name, provider.name()))?;
+
+        process::add_secret(&value);
+        self.values.borrow_mut().insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Replaces every `{{ secrets.NAME }}` in `template` with its value
+    pub fn render(&self, template: &str) -> Result<String> {
+        let mut rendered = String::with_capacity(template.len());
+        let mut last = 0;
+        for (range, name) in references(template) {
+            rendered.push_str(&template[last..range.start]);
+            rendered.push_str(&self.get(name)?);
+            last = range.end;
+        }
+        rendered.push_str(&template[last..]);
+        Ok(rendered)
+    }
+}
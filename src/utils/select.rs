@@ -0,0 +1,184 @@
+use std::io::{self, IsTerminal};
+use anyhow::Result;
+use colored::*;
+use dialoguer::{theme::ColorfulTheme, MultiSelect};
+use crate::core::{check::closest_match, config::{DottyConfig, FileMapping, Package}};
+use crate::utils::{output::print_status, prompt::read_line};
+
+/// Which packages are installed and which file mappings are copied,
+/// by their position in the configuration
+#[derive(Debug, Clone)]
+pub struct Selection {
+    packages: Vec<bool>,
+    files: Vec<Vec<bool>>,
+}
+
+impl Selection {
+    /// Everything in the configuration
+    pub fn all(config: &DottyConfig) -> Self {
+        Self {
+            packages: vec![true; config.packages.len()],
+            files: config.packages.iter().map(|p| vec![true; p.files.len()]).collect(),
+        }
+    }
+
+    pub fn package(&self, package: usize) -> bool {
+        self.packages.get(package).copied().unwrap_or(false)
+    }
+
+    pub fn file(&self, package: usize, mapping: usize) -> bool {
+        self.files.get(package).and_then(|files| files.get(mapping)).copied().unwrap_or(false)
+    }
+
+    pub fn set_package(&mut self, package: usize, selected: bool) {
+        if let Some(entry) = self.packages.get_mut(package) {
+            *entry = selected;
+        }
+    }
+
+    pub fn set_file(&mut self, package: usize, mapping: usize, selected: bool) {
+        if let Some(entry) = self.files.get_mut(package).and_then(|files| files.get_mut(mapping)) {
+            *entry = selected;
+        }
+    }
+
+    /// Keeps the packages named in `only` (all if empty) that are not in `except`,
+    /// along with their files
+    pub fn filter(&mut self, config: &DottyConfig, only: &[String], except: &[String]) -> Result<()> {
+        let names: Vec<&str> = config.packages.iter().map(|p| p.name.as_str()).collect();
+        for name in only.iter().chain(except) {
+            if !names.contains(&name.as_str()) {
+                match closest_match(name, &names) {
+                    Some(close) => anyhow::bail!("No package named `{}` in the configuration, did you mean `{}`?", name, close),
+                    None => anyhow::bail!("No package named `{}` in the configuration", name),
+                }
+            }
+        }
+
+        for (i, package) in config.packages.iter().enumerate() {
+            let keep = (only.is_empty() || only.contains(&package.name)) && !except.contains(&package.name);
+            if !keep {
+                self.packages[i] = false;
+                self.files[i].iter_mut().for_each(|f| *f = false);
+            }
+        }
+        Ok(())
+    }
+
+    /// Leaves out every package, only files are copied
+    pub fn without_packages(&mut self) {
+        self.packages.iter_mut().for_each(|p| *p = false);
+    }
+
+    /// Leaves out every file mapping, only packages are installed
+    pub fn without_files(&mut self) {
+        self.files.iter_mut().flatten().for_each(|f| *f = false);
+    }
+
+    /// Selected packages of `config`
+    pub fn packages<'a>(&self, config: &'a DottyConfig) -> Vec<&'a Package> {
+        config.packages.iter().enumerate().filter(|(i, _)| self.package(*i)).map(|(_, p)| p).collect()
+    }
+
+    /// Selected file mappings of `config`, with the package they belong to
+    pub fn files<'a>(&self, config: &'a DottyConfig) -> Vec<(&'a Package, &'a FileMapping)> {
+        config.packages
+            .iter()
+            .enumerate()
+            .flat_map(|(i, package)| {
+                package.files.iter().enumerate()
+                    .filter(move |(j, _)| self.file(i, *j))
+                    .map(move |(_, mapping)| (package, mapping))
+            })
+            .collect()
+    }
+}
+
+/// One line of the selector
+pub struct Item {
+    pub label: String,
+    pub status: String,
+    /// Shown below the previous item, for file mappings of a package
+    pub nested: bool,
+    /// Ticked when the list is shown
+    pub selected: bool,
+}
+
+impl Item {
+    fn render(&self) -> String {
+        let indent = if self.nested { "    " } else { "" };
+        format!("{}{} {}", indent, self.label, format!("({})", self.status).bright_black())
+    }
+}
+
+/// Dumb terminals cannot redraw the checkbox list
+fn is_capable_terminal() -> bool {
+    io::stdout().is_terminal()
+        && io::stdin().is_terminal()
+        && std::env::var("TERM").is_ok_and(|term| !term.is_empty() && term != "dumb")
+}
+
+/// Lets the user tick items, starting from their `selected` state.
+///
+/// Returns which items are selected, or `None` if the selection was cancelled.
+pub fn select(prompt: &str, items: &[Item]) -> Result<Option<Vec<bool>>> {
+    if is_capable_terminal() {
+        let labels: Vec<String> = items.iter().map(Item::render).collect();
+        let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("{} (space to toggle, enter to confirm, esc to cancel)", prompt))
+            .items(&labels)
+            .defaults(&items.iter().map(|item| item.selected).collect::<Vec<_>>())
+            .interact_opt()?;
+
+        return Ok(chosen.map(|chosen| (0..items.len()).map(|i| chosen.contains(&i)).collect()));
+    }
+
+    select_numbered(prompt, items)
+}
+
+/// Fallback that prints a numbered list and reads numbers and ranges to toggle
+fn select_numbered(prompt: &str, items: &[Item]) -> Result<Option<Vec<bool>>> {
+    let mut selected: Vec<bool> = items.iter().map(|item| item.selected).collect();
+    loop {
+        println!("\n{}", prompt);
+        for (i, item) in items.iter().enumerate() {
+            let mark = if selected[i] { "[x]" } else { "[ ]" };
+            println!("{:>3}) {} {}", i + 1, mark, item.render());
+        }
+
+        print!("\nToggle items (e.g. 1 3-5, `all`, `none`), press enter when done or `q` to cancel: ");
+        let Some(input) = read_line() else {
+            return Ok(None);
+        };
+
+        match input.as_str() {
+            "" => return Ok(Some(selected)),
+            "q" | "quit" => return Ok(None),
+            "all" => selected.iter_mut().for_each(|s| *s = true),
+            "none" => selected.iter_mut().for_each(|s| *s = false),
+            _ => match parse_numbers(&input, items.len()) {
+                Some(numbers) => numbers.into_iter().for_each(|i| selected[i] = !selected[i]),
+                None => print_status(format!("Enter numbers between 1 and {}", items.len())),
+            },
+        }
+    }
+}
+
+/// Parses `1 3-5` into zero based indices, `None` if anything is out of range
+fn parse_numbers(input: &str, count: usize) -> Option<Vec<usize>> {
+    let mut numbers = Vec::new();
+    for part in input.split(|c: char| c == ',' || c.is_whitespace()).filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.parse::<usize>().ok()?, end.parse::<usize>().ok()?),
+            None => {
+                let number = part.parse::<usize>().ok()?;
+                (number, number)
+            }
+        };
+        if start == 0 || end > count || start > end {
+            return None;
+        }
+        numbers.extend(start - 1..end);
+    }
+    Some(numbers)
+}
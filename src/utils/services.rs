@@ -0,0 +1,184 @@
+use std::fmt;
+use anyhow::{Context, Result};
+use crate::core::config::{Service, ServiceScope};
+use crate::utils::install::backend::{ask_for_sudo_password, CommandRunner};
+
+/// A change made to a unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Mask,
+    Enable,
+    Start,
+}
+
+impl Action {
+    fn command(&self) -> &'static str {
+        match self {
+            Action::Mask => "mask",
+            Action::Enable => "enable",
+            Action::Start => "start",
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.command())
+    }
+}
+
+/// The actions a service asks for, in the order they are applied
+pub fn wanted(service: &Service) -> Vec<Action> {
+    [(service.mask, Action::Mask), (service.enable, Action::Enable), (service.start, Action::Start)]
+        .into_iter()
+        .filter(|(wanted, _)| *wanted)
+        .map(|(_, action)| action)
+        .collect()
+}
+
+/// What systemd reports about a unit
+#[derive(Debug, Clone)]
+pub struct UnitState {
+    /// Output of `systemctl is-enabled`, such as `enabled`, `disabled` or `masked`
+    pub enabled: String,
+    /// Output of `systemctl is-active`, such as `active` or `inactive`
+    pub active: String,
+}
+
+impl UnitState {
+    fn is_in_effect(&self, action: Action) -> bool {
+        match action {
+            Action::Mask => self.enabled.starts_with("masked"),
+            // Static and generated units cannot be enabled, they count as enabled
+            Action::Enable => matches!(self.enabled.as_str(),
+                "enabled" | "enabled-runtime" | "static" | "alias" | "indirect" | "generated"),
+            Action::Start => matches!(self.active.as_str(), "active" | "activating" | "reloading"),
+        }
+    }
+
+    /// Actions of `service` that are not in effect yet
+    pub fn pending(&self, service: &Service) -> Vec<Action> {
+        wanted(service).into_iter().filter(|action| !self.is_in_effect(*action)).collect()
+    }
+}
+
+/// Arguments selecting the systemd instance of `scope`
+fn scope_args(scope: ServiceScope) -> &'static [&'static str] {
+    match scope {
+        ServiceScope::User => &["--user"],
+        ServiceScope::System => &[],
+    }
+}
+
+/// Reads the state of the unit of `service`
+pub fn query(runner: &dyn CommandRunner, service: &Service) -> Result<UnitState> {
+    // Both commands exit unsuccessfully for disabled and inactive units, only the output matters
+    let read = |command: &str| -> Result<String> {
+        let mut args = scope_args(service.scope).to_vec();
+        args.extend([command, service.unit.as_str()]);
+        let output = runner.output("systemctl", &args)
+            .with_context(|| format!("Failed to query {}", service.unit))?;
+        Ok(match output.stdout.trim() {
+            "" => "unknown".to_string(),
+            state => state.to_string(),
+        })
+    };
+    Ok(UnitState { enabled: read("is-enabled")?, active: read("is-active")? })
+}
+
+/// Brings the unit of `service` to the requested state and returns the actions taken.
+///
+/// Actions already in effect are skipped, so applying twice changes nothing.
+pub fn apply(runner: &dyn CommandRunner, service: &Service) -> Result<Vec<Action>> {
+    let pending = query(runner, service)?.pending(service);
+    if pending.is_empty() {
+        return Ok(pending);
+    }
+
+    if service.scope == ServiceScope::System {
+        ask_for_sudo_password(runner)?;
+    }
+    for action in &pending {
+        let result = match service.scope {
+            ServiceScope::User => runner.run("systemctl", &["--user", action.command(), &service.unit]),
+            ServiceScope::System => runner.run("sudo", &["systemctl", action.command(), &service.unit]),
+        };
+        result.with_context(|| format!("Failed to {} {}", action, service.unit))?;
+    }
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::install::backend::fake::FakeRunner;
+
+    fn service(scope: ServiceScope, enable: bool, start: bool, mask: bool) -> Service {
+        Service { unit: "syncthing.service".to_string(), scope, enable, start, mask }
+    }
+
+    fn state(enabled: &str, active: &str) -> UnitState {
+        UnitState { enabled: enabled.to_string(), active: active.to_string() }
+    }
+
+    /// Stubs `systemctl is-enabled` and `is-active` for the user unit
+    fn systemctl(enabled: &str, active: &str) -> FakeRunner {
+        FakeRunner::new()
+            .with_output("systemctl --user is-enabled syncthing.service", &format!("{}\n", enabled))
+            .with_status("systemctl --user is-active syncthing.service", active == "active", &format!("{}\n", active))
+    }
+
+    #[test]
+    fn pending_actions() {
+        let enable_and_start = service(ServiceScope::User, true, true, false);
+        assert_eq!(state("disabled", "inactive").pending(&enable_and_start), [Action::Enable, Action::Start]);
+        assert_eq!(state("enabled", "inactive").pending(&enable_and_start), [Action::Start]);
+        assert_eq!(state("enabled", "active").pending(&enable_and_start), []);
+        // Static units cannot be enabled and count as enabled
+        assert_eq!(state("static", "active").pending(&enable_and_start), []);
+        // A masked unit is neither enabled nor running
+        assert_eq!(state("masked", "inactive").pending(&enable_and_start), [Action::Enable, Action::Start]);
+
+        let mask = service(ServiceScope::System, false, false, true);
+        assert_eq!(state("enabled", "active").pending(&mask), [Action::Mask]);
+        assert_eq!(state("masked", "inactive").pending(&mask), []);
+        assert_eq!(state("masked-runtime", "inactive").pending(&mask), []);
+    }
+
+    #[test]
+    fn query_reads_systemctl() {
+        let unit = query(&systemctl("disabled", "inactive"), &service(ServiceScope::User, true, false, false)).unwrap();
+        assert_eq!(unit.enabled, "disabled");
+        assert_eq!(unit.active, "inactive");
+
+        // Units systemd does not know print nothing on stdout
+        let unit = query(&FakeRunner::new(), &service(ServiceScope::User, true, false, false)).unwrap();
+        assert_eq!(unit.enabled, "unknown");
+    }
+
+    #[test]
+    fn apply_in_effect_issues_no_commands() {
+        let runner = systemctl("enabled", "active");
+        let actions = apply(&runner, &service(ServiceScope::User, true, true, false)).unwrap();
+        assert!(actions.is_empty());
+        assert!(runner.runs().is_empty());
+    }
+
+    #[test]
+    fn apply_runs_only_pending_actions() {
+        let runner = systemctl("enabled", "inactive");
+        let actions = apply(&runner, &service(ServiceScope::User, true, true, false)).unwrap();
+        assert_eq!(actions, [Action::Start]);
+        assert_eq!(runner.runs(), ["systemctl --user start syncthing.service"]);
+    }
+
+    #[test]
+    fn system_units_use_sudo() {
+        let runner = FakeRunner::new()
+            .with_output("systemctl is-enabled cups.service", "enabled\n")
+            .with_output("systemctl is-active cups.service", "active\n");
+        let cups = Service { unit: "cups.service".to_string(), ..service(ServiceScope::System, false, false, true) };
+        assert_eq!(apply(&runner, &cups).unwrap(), [Action::Mask]);
+        assert_eq!(runner.runs(), ["sudo -v", "sudo systemctl mask cups.service"]);
+    }
+}
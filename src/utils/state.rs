@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::core::config::{DottyConfig, Source};
+
+/// A package dotty installed, as opposed to one that was already there
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstalledPackage {
+    /// Name handed to the package manager
+    pub name: String,
+    pub source: Source,
+    /// Configured package it was installed for
+    pub package: String,
+}
+
+/// A file, symlink or directory dotty deployed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeployedFile {
+    pub path: PathBuf,
+    /// Configured package the file belongs to
+    pub package: String,
+    /// Rendered from a template, so it may hold secret values
+    #[serde(default)]
+    pub rendered: bool,
+}
+
+/// What dotty has done to this machine, kept across runs so packages and
+/// files that leave the configuration can be removed with `dotty prune`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MachineState {
+    #[serde(default)]
+    pub packages: Vec<InstalledPackage>,
+    #[serde(default)]
+    pub files: Vec<DeployedFile>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl MachineState {
+    /// Default location, `~/.local/state/dotty/state.json`
+    pub fn default_path() -> Result<PathBuf> {
+        let dir = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .ok_or_else(|| anyhow::anyhow!("Failed to get state directory"))?;
+        Ok(dir.join("dotty").join("state.json"))
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::default_path()?)
+    }
+
+    /// Reads the state from `path`, a missing file is an empty state
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let mut state: MachineState = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => MachineState::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        state.path = path.to_path_buf();
+        Ok(state)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(&self.path, contents + "\n")
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    pub fn record_package(&mut self, package: InstalledPackage) {
+        self.packages.retain(|p| !(p.name == package.name && p.source == package.source));
+        self.packages.push(package);
+    }
+
+    pub fn record_file(&mut self, file: DeployedFile) {
+        self.files.retain(|f| f.path != file.path);
+        self.files.push(file);
+    }
+
+    /// Whether `path` was last deployed as a rendered template
+    pub fn is_rendering(&self, path: &Path) -> bool {
+        self.files.iter().any(|f| f.rendered && f.path == path)
+    }
+
+    pub fn forget_package(&mut self, name: &str, source: Source) {
+        self.packages.retain(|p| !(p.name == name && p.source == source));
+    }
+
+    pub fn forget_file(&mut self, path: &Path) {
+        self.files.retain(|f| f.path != path);
+    }
+
+    /// Installed packages whose configured package is no longer declared
+    pub fn orphaned_packages(&self, config: &DottyConfig) -> Vec<&InstalledPackage> {
+        self.packages
+            .iter()
+            .filter(|p| !config.packages.iter().any(|declared| declared.name == p.package))
+            .collect()
+    }
+
+    /// Deployed files that are not at or below the destination of any mapping anymore
+    pub fn orphaned_files(&self, config: &DottyConfig) -> Vec<&DeployedFile> {
+        let declared: Vec<PathBuf> = config
+            .get_all_file_mappings()
+            .iter()
+            .map(|mapping| mapping.destination_path())
+            .collect();
+        self.files.iter().filter(|f| !declared.iter().any(|d| f.path.starts_with(d))).collect()
+    }
+}
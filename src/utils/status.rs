@@ -0,0 +1,127 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use anyhow::Result;
+use walkdir::WalkDir;
+use crate::core::config::FileMapping;
+use crate::utils::{permissions, render::SourceReader};
+
+/// How a deployed mapping compares to the repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// Content, permissions and ownership all match
+    InSync,
+    /// The destination does not exist yet
+    Missing,
+    /// Some content differs from the repository
+    Modified,
+    /// Content matches but permissions or ownership do not
+    Drifted,
+}
+
+impl State {
+    pub fn label(&self) -> &'static str {
+        match self {
+            State::InSync => "in sync",
+            State::Missing => "not deployed",
+            State::Modified => "modified",
+            State::Drifted => "drifted",
+        }
+    }
+}
+
+pub struct MappingStatus {
+    pub state: State,
+    pub issues: Vec<String>,
+}
+
+/// Compares a mapping's destination against its source in the repository,
+/// encrypted and templated sources are compared as `reader` produces them
+pub fn mapping_status(repo_path: &Path, mapping: &FileMapping, reader: &SourceReader) -> Result<MappingStatus> {
+    let source = mapping.source_path(repo_path);
+    let destination = mapping.destination_path();
+
+    if !destination.exists() {
+        return Ok(MappingStatus { state: State::Missing, issues: Vec::new() });
+    }
+
+    let mut issues = if mapping.is_generated() {
+        generated_differences(&source, &destination, mapping, reader)?
+    } else {
+        content_differences(&source, &destination)?
+    };
+    let mut drift = permissions::mapping_drift(&source, &destination, mapping)?;
+    // Decrypted and rendered files are deployed as 0600 unless the mapping asks otherwise
+    if mapping.is_generated() && mapping.mode.is_none() {
+        let mode = std::fs::metadata(&destination)?.permissions().mode() & 0o7777;
+        if mode & 0o077 != 0 {
+            let kind = if mapping.encrypted { "decrypted file" } else { "rendered template" };
+            drift.push(format!("{}: mode is {:04o}, expected 0600 for a {}", destination.display(), mode, kind));
+        }
+    }
+
+    let state = if !issues.is_empty() {
+        State::Modified
+    } else if !drift.is_empty() {
+        State::Drifted
+    } else {
+        State::InSync
+    };
+    issues.extend(drift);
+
+    Ok(MappingStatus { state, issues })
+}
+
+fn generated_differences(source: &Path, destination: &Path, mapping: &FileMapping, reader: &SourceReader) -> Result<Vec<String>> {
+    let expected = reader.read(mapping, source)?;
+    Ok(if std::fs::read(destination)? == expected {
+        Vec::new()
+    } else {
+        vec![format!("{}: content differs", destination.display())]
+    })
+}
+
+fn content_differences(source: &Path, destination: &Path) -> Result<Vec<String>> {
+    if !source.is_dir() {
+        return Ok(if files_equal(source, destination)? {
+            Vec::new()
+        } else {
+            vec![format!("{}: content differs", destination.display())]
+        });
+    }
+
+    let mut issues = Vec::new();
+    for entry in WalkDir::new(source).into_iter().filter_map(|e| e.ok()) {
+        let relative = entry.path().strip_prefix(source)?;
+        let target = destination.join(relative);
+
+        if entry.path_is_symlink() {
+            let expected = std::fs::read_link(entry.path())?;
+            match std::fs::read_link(&target) {
+                Ok(actual) if actual == expected => {}
+                Ok(actual) => issues.push(format!(
+                    "{}: links to {}, expected {}", target.display(), actual.display(), expected.display())),
+                Err(_) => issues.push(format!("{}: expected a symlink to {}", target.display(), expected.display())),
+            }
+            continue;
+        }
+
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        if !target.exists() {
+            issues.push(format!("{}: missing", target.display()));
+        } else if !files_equal(entry.path(), &target)? {
+            issues.push(format!("{}: content differs", target.display()));
+        }
+    }
+
+    Ok(issues)
+}
+
+fn files_equal(a: &Path, b: &Path) -> Result<bool> {
+    if !b.is_file() {
+        return Ok(false);
+    }
+    Ok(std::fs::read(a)? == std::fs::read(b)?)
+}
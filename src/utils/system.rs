@@ -1,18 +1,23 @@
 use std::process::Command;
+use clap::ValueEnum;
 use std::path::PathBuf;
-use std::io::{self, Write};
+use crate::core::config::Distro;
 use crate::utils::{
     install::aur::install_aur_helper,
-    output::print_status,
+    output::print_error,
+    process::print_failure_details,
+    prompt::read_line,
 };
 
 /// Represents available AUR helpers
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum AURHelper {
     /// Paru AUR Helper
     Paru,
     /// Yay AUR helper
     Yay,
+    /// Dotty's own makepkg based builder
+    Builtin,
     /// None (No AUR helper found)
     None,
 }
@@ -22,7 +27,17 @@ impl AURHelper {
         match self {
             AURHelper::Paru => Some("paru"),
             AURHelper::Yay => Some("yay"),
-            AURHelper::None => None,
+            AURHelper::Builtin | AURHelper::None => None,
+        }
+    }
+
+    /// Name shown in package lists
+    pub fn name(&self) -> &str {
+        match self {
+            AURHelper::Paru => "paru",
+            AURHelper::Yay => "yay",
+            AURHelper::Builtin => "built-in builder",
+            AURHelper::None => "none",
         }
     }
 
@@ -36,6 +51,7 @@ impl AURHelper {
         match self {
             AURHelper::Paru => "Paru (Feature-rich AUR helper written in Rust)",
             AURHelper::Yay => "Yay (Yet Another Yogurt - AUR Helper written in Go)",
+            AURHelper::Builtin => "Build with dotty (clones and runs makepkg, no helper installed)",
             AURHelper::None => "None",
         }
     }
@@ -46,33 +62,36 @@ pub fn prompt_aur_helper_installation() -> Option<AURHelper> {
     println!("\nNo AUR helper found. Choose one to install:");
     println!("1) {}", AURHelper::Paru.description());
     println!("2) {}", AURHelper::Yay.description());
-    println!("3) Skip (but AUR packages won't be installed)"); //TODO: manually install aur packages... (clone)
+    println!("3) {}", AURHelper::Builtin.description());
+    println!("4) Skip (but AUR packages won't be installed)");
     
-    print!("\nEnter your choice [1-3]: ");
-    io::stdout().flush().unwrap();
-
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-
-    match input.trim() {
-        "1" => {
-            if let Err(e) = install_aur_helper("paru") {
-                print_status(&format!("Failed to install paru: {}", e));
-                return None;
-            }
-            Some(AURHelper::Paru)
-        }
-        "2" => {
-            if let Err(e) = install_aur_helper("yay") {
-                print_status(&format!("Failed to install yay: {}", e));
-                return None;
-            }
-            Some(AURHelper::Yay)
-        }
+    print!("\nEnter your choice [1-4]: ");
+
+    match read_line().as_deref() {
+        Some("1") => ensure_aur_helper(AURHelper::Paru),
+        Some("2") => ensure_aur_helper(AURHelper::Yay),
+        Some("3") => Some(AURHelper::Builtin),
         _ => None,
     }
 }
 
+/// Installs paru or yay if `helper` names one that is missing
+pub fn ensure_aur_helper(helper: AURHelper) -> Option<AURHelper> {
+    let Some(command) = helper.command() else {
+        return Some(helper);
+    };
+    if has_command(command) {
+        return Some(helper);
+    }
+
+    if let Err(e) = install_aur_helper(command) {
+        print_error(format!("Failed to install {}: {}", command, e));
+        print_failure_details(&e);
+        return None;
+    }
+    Some(helper)
+}
+
 /// Detects the available AUR helper, prioritizing paru over yay
 pub fn detect_aur_helper() -> AURHelper {
     // check for paru first. it's the preferred helper
@@ -117,4 +136,35 @@ pub fn get_aur_helper_path() -> Option<PathBuf> {
                 })
         }
     }
-} 
\ No newline at end of file
+}
+
+/// Detects the distribution family from `/etc/os-release`
+pub fn detect_distro() -> Option<Distro> {
+    let contents = std::fs::read_to_string("/etc/os-release")
+        .or_else(|_| std::fs::read_to_string("/usr/lib/os-release"))
+        .ok()?;
+    distro_from_os_release(&contents)
+}
+
+/// Matches `ID` first, then every entry of `ID_LIKE` in order
+pub fn distro_from_os_release(contents: &str) -> Option<Distro> {
+    let field = |key: &str| {
+        contents
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(k, _)| k.trim() == key)
+            .map(|(_, v)| v.trim().trim_matches(|c| c == '"' || c == '\'').to_lowercase())
+    };
+
+    let id = field("ID").unwrap_or_default();
+    let like = field("ID_LIKE").unwrap_or_default();
+
+    std::iter::once(id.as_str())
+        .chain(like.split_whitespace())
+        .find_map(|id| match id {
+            "arch" | "archarm" => Some(Distro::Arch),
+            "debian" | "ubuntu" => Some(Distro::Debian),
+            "fedora" | "rhel" | "centos" => Some(Distro::Fedora),
+            _ => None,
+        })
+}
@@ -0,0 +1,164 @@
+use std::fmt;
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::core::config::SystemSettings;
+use crate::utils::install::backend::{ask_for_sudo_password, CommandRunner};
+
+const SHELLS: &str = "/etc/shells";
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+const LOCALTIME: &str = "/etc/localtime";
+/// Arch and Fedora keep the locale in `locale.conf`, Debian in `default/locale`
+const LOCALE_FILES: &[&str] = &["/etc/locale.conf", "/etc/default/locale"];
+
+/// One entry of the `system:` section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Setting {
+    Shell(String),
+    Group(String),
+    Locale(String),
+    Timezone(String),
+}
+
+impl Setting {
+    /// The value the setting asks for
+    pub fn value(&self) -> &str {
+        match self {
+            Setting::Shell(value) | Setting::Group(value) | Setting::Locale(value) | Setting::Timezone(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Setting::Shell(shell) => write!(f, "login shell {}", shell),
+            Setting::Group(group) => write!(f, "member of {}", group),
+            Setting::Locale(locale) => write!(f, "locale {}", locale),
+            Setting::Timezone(timezone) => write!(f, "timezone {}", timezone),
+        }
+    }
+}
+
+/// The settings of `system` in the order they are applied
+pub fn settings(system: &SystemSettings) -> Vec<Setting> {
+    let mut settings = Vec::new();
+    settings.extend(system.shell.clone().map(Setting::Shell));
+    settings.extend(system.groups.iter().cloned().map(Setting::Group));
+    settings.extend(system.locale.clone().map(Setting::Locale));
+    settings.extend(system.timezone.clone().map(Setting::Timezone));
+    settings
+}
+
+/// How a setting compares to this machine
+pub struct SettingState {
+    pub setting: Setting,
+    /// What the machine has now, `None` if it could not be read
+    pub current: Option<String>,
+    /// Why the setting cannot be applied, such as a shell missing from `/etc/shells`
+    pub problem: Option<String>,
+}
+
+impl SettingState {
+    pub fn is_applied(&self) -> bool {
+        self.current.as_deref() == Some(self.setting.value())
+    }
+}
+
+/// Locale names are compared the way glibc normalizes them, so `en_US.utf8` is `en_US.UTF-8`
+fn normalize_locale(locale: &str) -> String {
+    locale.to_lowercase().replace('-', "")
+}
+
+/// Reads and changes the system settings of the current user
+pub struct Machine<'a> {
+    runner: &'a dyn CommandRunner,
+    user: String,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Result<Self> {
+        let output = runner.output("id", &["-un"]).context("Failed to read the current user")?;
+        let user = output.stdout.trim().to_string();
+        if !output.success || user.is_empty() {
+            anyhow::bail!("Failed to read the current user");
+        }
+        Ok(Self { runner, user })
+    }
+
+    /// Compares `setting` with the machine without changing anything
+    pub fn inspect(&self, setting: &Setting) -> Result<SettingState> {
+        let (current, problem) = match setting {
+            Setting::Shell(shell) => {
+                let shells = std::fs::read_to_string(SHELLS).unwrap_or_default();
+                let problem = (!shells.lines().any(|line| line.trim() == shell))
+                    .then(|| format!("{} is not listed in {}", shell, SHELLS));
+                (self.shell()?, problem)
+            }
+            Setting::Group(group) => {
+                let exists = self.runner.output("getent", &["group", group]).is_ok_and(|o| o.success);
+                let problem = (!exists).then(|| format!("group {} does not exist", group));
+                (self.groups()?.contains(group).then(|| group.clone()), problem)
+            }
+            Setting::Locale(locale) => {
+                // Only checked where `locale` is available to list what is generated
+                let problem = self.runner.output("locale", &["-a"]).ok()
+                    .filter(|output| output.success)
+                    .filter(|output| !output.stdout.lines().any(|l| normalize_locale(l) == normalize_locale(locale)))
+                    .map(|_| format!("locale {} is not generated", locale));
+                let current = current_locale().map(|current| {
+                    if normalize_locale(&current) == normalize_locale(locale) { locale.clone() } else { current }
+                });
+                (current, problem)
+            }
+            Setting::Timezone(timezone) => {
+                let problem = (!Path::new(ZONEINFO).join(timezone).is_file())
+                    .then(|| format!("unknown timezone {}", timezone));
+                (current_timezone(), problem)
+            }
+        };
+        Ok(SettingState { setting: setting.clone(), current, problem })
+    }
+
+    /// Changes `setting` on the machine, the caller checks whether it is applied already
+    pub fn apply(&self, setting: &Setting) -> Result<()> {
+        ask_for_sudo_password(self.runner)?;
+        let result = match setting {
+            Setting::Shell(shell) => self.runner.run("sudo", &["chsh", "-s", shell, &self.user]),
+            Setting::Group(group) => self.runner.run("sudo", &["usermod", "-aG", group, &self.user]),
+            Setting::Locale(locale) => self.runner.run("sudo", &["localectl", "set-locale", &format!("LANG={}", locale)]),
+            Setting::Timezone(timezone) if self.runner.has_command("timedatectl") => {
+                self.runner.run("sudo", &["timedatectl", "set-timezone", timezone])
+            }
+            // Without systemd the link is all there is to the timezone
+            Setting::Timezone(timezone) => {
+                let zone = Path::new(ZONEINFO).join(timezone);
+                self.runner.run("sudo", &["ln", "-sf", &zone.to_string_lossy(), LOCALTIME])
+            }
+        };
+        result.with_context(|| format!("Failed to set {}", setting))
+    }
+
+    fn shell(&self) -> Result<Option<String>> {
+        let output = self.runner.output("getent", &["passwd", &self.user])?;
+        Ok(output.stdout.trim().split(':').nth(6).map(str::to_string))
+    }
+
+    /// Groups of the user in the group database, which includes groups added since login
+    fn groups(&self) -> Result<Vec<String>> {
+        let output = self.runner.output("id", &["-nG", &self.user])?;
+        Ok(output.stdout.split_whitespace().map(str::to_string).collect())
+    }
+}
+
+fn current_locale() -> Option<String> {
+    LOCALE_FILES.iter()
+        .filter_map(|file| std::fs::read_to_string(file).ok())
+        .flat_map(|contents| contents.lines().map(str::to_string).collect::<Vec<_>>())
+        .find_map(|line| line.trim().strip_prefix("LANG=").map(|value| value.trim_matches('"').to_string()))
+}
+
+fn current_timezone() -> Option<String> {
+    let target = std::fs::read_link(LOCALTIME).ok()?;
+    let target = target.to_string_lossy();
+    target.split_once("zoneinfo/").map(|(_, zone)| zone.to_string())
+}